@@ -10,7 +10,7 @@ pub mod auth {
     use std::str::FromStr;
 
     use serde::{Serialize, Deserialize};
-    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
     pub struct Token(pub u128);
     impl FromStr for Token {
         type Err = <u128 as FromStr>::Err;
@@ -40,6 +40,16 @@ pub mod contest {
     pub struct Time {
         pub start: std::time::SystemTime,
         pub duration: Option<std::time::Duration>,
+        // scoreboard is frozen for this long before the end
+        #[serde(default)]
+        pub freeze: Option<std::time::Duration>,
+    }
+
+    impl Time {
+        pub fn freeze_time(&self) -> Option<std::time::SystemTime> {
+            let duration = self.duration?;
+            Some(self.start + duration.saturating_sub(self.freeze?))
+        }
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -49,6 +59,12 @@ pub mod contest {
         Set(Option<std::time::Duration>),
     }    
 
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub enum UpdateTime {
+        Start(std::time::SystemTime),
+        Duration(UpdateDuration),
+    }
+
     pub type File = [u8];
 }
 
@@ -63,12 +79,33 @@ pub mod msg {
         JudgeSolution(solution::Solution),
         UpdateTests(Box<contest::File>),
         InitFailed,
+        // the token is wrong or revoked, the connection is closed after it
+        Unauthorized,
+        // the id belongs to a machine with another token, the connection is closed after it
+        IdTaken,
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
     pub enum MachineToServer {
-        Init,
+        // the first message of every connection, the id is kept by the machine between connections,
+        // the token is issued by an admin
+        Init {
+            id: crate::machine::Id,
+            token: crate::auth::Token,
+            // solutions the machine judges at once
+            slots: u16,
+            langs: Vec<solution::LangInfo>,
+            // solutions received and not answered yet, kept by the server after a reconnect
+            judging: Vec<solution::Id>,
+        },
+        Heartbeat(crate::machine::Health),
         JudgeResult(solution::JudgeResult),
+        Progress {
+            solution_id: solution::Id,
+            progress: solution::Progress,
+        },
+        // sent after every tests update, empty if all problems are fine
+        TestsUpdated(Vec<crate::tests::PackageError>),
     }
 
     pub mod admin_to_server {
@@ -79,18 +116,37 @@ pub mod msg {
                 use super::*;
                 pub type Update = Box<File>;
             }
-            pub mod time {
+            pub mod statements {
                 use super::*;
                 pub type Update = Box<File>;
             }
+            pub mod time {
+                pub type Update = crate::contest::UpdateTime;
+            }
             pub mod state {
                 use super::*;
                 pub type SetReady = crate::contest::Time;
             }
+            pub mod rules {
+                pub type Set = crate::scoreboard::Rules;
+            }
+            pub mod scoreboard {
+                pub type Unfreeze = crate::scoreboard::Unfreeze;
+            }
         }
         pub mod tokens {
             pub type Get = Box<str>;
         }
+        pub mod machines {
+            // machine name
+            pub type IssueToken = Box<str>;
+            pub type Revoke = Box<str>;
+        }
+        pub mod participants {
+            use crate::participant::New;
+            pub type Add = New;
+            pub type Import = Vec<New>;
+        }
 
     }
 
@@ -99,6 +155,46 @@ pub mod msg {
         Ok(T),
         Err(Box<str>),
     }
+
+    pub mod client_to_server {
+        pub mod auth {
+            use serde::{Serialize, Deserialize};
+
+            #[derive(Clone, Debug, Serialize, Deserialize)]
+            pub enum Login {
+                Password {
+                    login: Box<str>,
+                    password: Box<str>,
+                },
+                InviteCode(Box<str>),
+            }
+        }
+        pub mod submissions {
+            use serde::{Serialize, Deserialize};
+            use crate::solution::{Lang, ProblemNum};
+
+            #[derive(Clone, Debug, Serialize, Deserialize)]
+            pub struct Submit {
+                pub code: String,
+                pub lang: Lang,
+                pub problem_number: ProblemNum,
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub enum ServerToClient<T> {
+        Ok(T),
+        Err(Box<str>),
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub enum ServerToClientEvent {
+        ContestStarted,
+        ContestPaused,
+        ContestResumed,
+        ContestFinished,
+    }
     // #[derive(Debug, Serialize, Deserialize)]
     // pub enum AdminToServer {
     //     UpdateContest {
@@ -116,6 +212,23 @@ pub mod msg {
     // }
 }
 
+pub mod participant {
+    use serde::{Serialize, Deserialize};
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct New {
+        pub login: Box<str>,
+        pub name: Box<str>,
+        pub password: Option<Box<str>>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Credentials {
+        pub login: Box<str>,
+        pub invite_code: Option<Box<str>>,
+    }
+}
+
 pub mod solution {
     use serde::{Serialize, Deserialize};
 
@@ -125,27 +238,89 @@ pub mod solution {
     #[derive(Clone, Serialize, Deserialize, Eq, PartialEq, Debug)]
     pub enum Verdict {
         Ok, Wa, Re, Tl, Ml, Pe, Ce,
+        // output limit exceeded
+        Ol,
+        // idleness limit, the wall time ran out while the solution was not using the cpu
+        Il,
+        // security violation, the solution made a denied system call
+        Sv,
+        // the checker or the machine failed, the solution is not to blame
+        Fail,
+        // not run because an earlier test failed
+        Skipped,
+    }
+
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct TestResult {
+        pub test_number: u16,
+        pub verdict: Verdict,
+        // milliseconds and kilobytes
+        pub time: u64,
+        pub memory: u64,
+        pub comment: Box<str>,
+        pub exit_code: Option<i32>,
+        pub signal: Option<i32>,
+        // share of the test points given by the checker, 0.0..=1.0
+        #[serde(default)]
+        pub points: Option<f64>,
+        // stderr of a runtime error, for admins only
+        #[serde(default)]
+        pub stderr: Option<Box<str>>,
+    }
+
+    // live state of a solution on a machine, until its result comes
+    #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+    pub enum Progress {
+        Compiling,
+        // the first test that has not finished yet
+        Running {
+            test: u16,
+            test_count: u16,
+        },
+        // all tests have run, the result is being put together
+        Checking,
+    }
+
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct GroupResult {
+        pub group: u16,
+        pub points: u32,
+        pub passed: bool,
+        // a dependency group was not passed
+        pub skipped: bool,
     }
 
     #[derive(Clone, Serialize, Deserialize, Debug)]
     pub struct JudgeResult {
         pub solution_id: Id,
         pub verdict: Verdict,
-        pub score: u8,
+        pub score: u32,
         pub problem_number: ProblemNum,
+        // maxima over the tests: milliseconds of cpu time and kilobytes of peak rss
+        #[serde(default)]
+        pub time: u64,
+        #[serde(default)]
+        pub memory: u64,
+        #[serde(default)]
+        pub tests: Vec<TestResult>,
+        #[serde(default)]
+        pub failed_test: Option<u16>,
+        #[serde(default)]
+        pub groups: Vec<GroupResult>,
+        // compiler stderr of a compilation error
+        #[serde(default)]
+        pub compile_output: Option<Box<str>>,
     }
 
-    #[derive(Clone, Debug, Serialize, Deserialize)]
-    pub enum Lang {
-        Cpp,
-    }
+    // id of a language in the machine registry, e.g. "cpp17" or "python3"
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+    #[serde(transparent)]
+    pub struct Lang(pub Box<str>);
 
-    impl Lang {
-        pub fn file_ext(&self) -> &'static str {
-            match self {
-                Self::Cpp => "cpp",
-            }
-        }
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct LangInfo {
+        pub lang: Lang,
+        pub name: Box<str>,
     }
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -157,13 +332,275 @@ pub mod solution {
         pub id: Id,
     }
 
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct Info {
+        pub id: Id,
+        pub participant: Box<str>,
+        pub problem_number: ProblemNum,
+        pub lang: Lang,
+        pub time: std::time::SystemTime,
+        pub result: Option<JudgeResult>,
+        // none before a machine takes the solution
+        #[serde(default)]
+        pub progress: Option<Progress>,
+    }
+
+}
+
+pub mod machine {
+    use std::time::Duration;
+    use serde::{Serialize, Deserialize};
+
+    use crate::solution;
+
+    pub type Id = u16;
+
+    pub const HEARTBEAT_PERIOD: Duration = Duration::from_secs(5);
+    // a machine silent for this time is disconnected
+    pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(20);
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Health {
+        // one minute load average
+        pub load: f64,
+        // megabytes
+        pub free_disk: u64,
+        pub free_memory: u64,
+        // solutions being judged now
+        pub judging: Vec<solution::Id>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Info {
+        pub id: Id,
+        // of its token
+        pub name: Box<str>,
+        pub langs: Vec<solution::Lang>,
+        pub slots: u16,
+        pub tasks: usize,
+        pub health: Option<Health>,
+        // seconds since the last heartbeat
+        pub last_heartbeat: u64,
+    }
+}
+
+pub mod scoreboard {
+    use serde::{Serialize, Deserialize};
+    use crate::solution::ProblemNum;
+
+    pub const ICPC_PENALTY_MINUTES: u64 = 20;
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Rules {
+        #[default]
+        Icpc,
+        Ioi,
+    }
+
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+    pub enum Unfreeze {
+        All,
+        Problem(ProblemNum),
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Problem {
+        pub problem_number: ProblemNum,
+        pub solved: bool,
+        pub wrong_attempts: u32,
+        pub pending: u32,
+        pub solved_minute: Option<u64>,
+        pub score: u32,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Row {
+        pub place: usize,
+        pub participant: Box<str>,
+        pub solved: u16,
+        pub penalty: u64,
+        pub score: u32,
+        pub problems: Vec<Problem>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Scoreboard {
+        pub rules: Rules,
+        pub frozen: bool,
+        pub rows: Vec<Row>,
+    }
 }
 
 pub mod tests {
-    use serde::Deserialize;
+    use serde::{Serialize, Deserialize};
+    use crate::solution::ProblemNum;
+
+    // source files compiled on the machine into binaries with the same name without extension
+    pub const CHECKER: &str = "checker";
+    pub const INTERACTOR: &str = "interactor";
+    pub const VALIDATOR: &str = "validator";
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct PackageError {
+        pub problem_number: ProblemNum,
+        pub error: Box<str>,
+    }
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Policy {
+        // points only if every test of the group passes
+        #[default]
+        Complete,
+        // points are shared between the tests of the group
+        EachTest,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Judging {
+        // ICPC, the tests after the first failed one are skipped
+        StopOnFailure,
+        // IOI, every test is run
+        AllTests,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Checker {
+        // tests/<problem>/checker with the testlib protocol
+        External,
+        Exact,
+        // whitespace only separates tokens
+        Tokens,
+        // trailing spaces and empty lines at the end are ignored
+        Lines,
+        Float {
+            #[serde(default = "default_eps")]
+            abs_eps: f64,
+            #[serde(default = "default_eps")]
+            rel_eps: f64,
+        },
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Group {
+        pub first_test: u16,
+        pub last_test: u16,
+        pub points: u32,
+        // numbers of earlier groups that must be passed
+        #[serde(default)]
+        pub dependencies: Vec<u16>,
+        #[serde(default)]
+        pub policy: Policy,
+    }
 
     #[derive(Debug, Deserialize)]
     pub struct Config {
         pub test_count: u16,
+
+        // milliseconds
+        #[serde(default = "default_time_limit")]
+        pub time_limit: u64,
+        #[serde(default)]
+        pub wall_time_limit: Option<u64>,
+
+        // megabytes
+        #[serde(default = "default_memory_limit")]
+        pub memory_limit: u64,
+        #[serde(default = "default_output_limit")]
+        pub output_limit: u64,
+
+        #[serde(default = "default_process_limit")]
+        pub process_limit: u64,
+
+        // without groups a solution gets 100 points for passing all tests
+        #[serde(default)]
+        pub groups: Vec<Group>,
+
+        // without it the external checker is used if the problem has one, tokens otherwise;
+        // an external checker gives partial points with exit code 7 and `points <share>` on stderr,
+        // the share of the test points is in 0..=1 and any other value fails the check
+        #[serde(default)]
+        pub checker: Option<Checker>,
+
+        // file name of the interactor in the problem directory, makes the problem interactive
+        #[serde(default)]
+        pub interactor: Option<Box<str>>,
+
+        // without it problems with groups run all tests, the others stop on failure
+        #[serde(default)]
+        pub judging: Option<Judging>,
+    }
+
+    impl Config {
+        pub fn judging(&self) -> Judging {
+            match self.judging {
+                Some(judging) => judging,
+                None if self.groups.is_empty() => Judging::StopOnFailure,
+                None => Judging::AllTests,
+            }
+        }
+
+        pub fn check_groups(&self) -> Result<(), String> {
+            for (number, group) in self.groups.iter().enumerate() {
+                if group.first_test == 0 || group.first_test > group.last_test || group.last_test > self.test_count {
+                    return Err(format!("group {number} has incorrect tests range"));
+                }
+                if let Some(d) = group.dependencies.iter().find(|d| **d as usize >= number) {
+                    return Err(format!("group {number} depends on group {d} which is not before it"));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn default_time_limit() -> u64 { 1000 }
+    fn default_memory_limit() -> u64 { 256 }
+    fn default_output_limit() -> u64 { 64 }
+    fn default_process_limit() -> u64 { 1 }
+    fn default_eps() -> f64 { 1e-6 }
+
+    #[cfg(test)]
+    mod config_tests {
+        use super::*;
+
+        fn config(groups: &str) -> Config {
+            serde_json::from_str(&format!(r#"{{"test_count": 10, "groups": {groups}}}"#)).unwrap()
+        }
+
+        #[test]
+        fn correct_groups() {
+            let config = config(r#"[
+                {"first_test": 1, "last_test": 1, "points": 0},
+                {"first_test": 2, "last_test": 5, "points": 40, "dependencies": [0]},
+                {"first_test": 6, "last_test": 10, "points": 60, "dependencies": [0, 1], "policy": "each_test"}
+            ]"#);
+            assert_eq!(config.check_groups(), Ok(()));
+            assert_eq!(config.groups[2].policy, Policy::EachTest);
+            assert_eq!(config.judging(), Judging::AllTests);
+        }
+
+        #[test]
+        fn incorrect_tests_range() {
+            for groups in [
+                r#"[{"first_test": 0, "last_test": 3, "points": 10}]"#,
+                r#"[{"first_test": 4, "last_test": 3, "points": 10}]"#,
+                r#"[{"first_test": 1, "last_test": 11, "points": 10}]"#,
+            ] {
+                assert_eq!(config(groups).check_groups(), Err("group 0 has incorrect tests range".to_string()));
+            }
+        }
+
+        #[test]
+        fn dependency_not_before_group() {
+            let itself = config(r#"[{"first_test": 1, "last_test": 10, "points": 100, "dependencies": [0]}]"#);
+            assert_eq!(itself.check_groups(), Err("group 0 depends on group 0 which is not before it".to_string()));
+            let later = config(r#"[
+                {"first_test": 1, "last_test": 5, "points": 50, "dependencies": [1]},
+                {"first_test": 6, "last_test": 10, "points": 50}
+            ]"#);
+            assert_eq!(later.check_groups(), Err("group 0 depends on group 1 which is not before it".to_string()));
+        }
     }
 }
\ No newline at end of file
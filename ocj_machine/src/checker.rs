@@ -0,0 +1,266 @@
+use std::{path::Path, process::Stdio, time::Duration};
+
+use ocj_config::{solution::Verdict, tests::Checker};
+
+use crate::sandbox;
+
+// testlib exit codes
+const OK: i32 = 0;
+const WA: i32 = 1;
+const PE: i32 = 2;
+const FAIL: i32 = 3;
+const DIRT: i32 = 4;
+const POINTS: i32 = 7;
+const UNEXPECTED_EOF: i32 = 8;
+
+const COMMENT_LIMIT: usize = 256;
+
+const LIMITS: sandbox::Limits = sandbox::Limits {
+    cpu_time: Duration::from_secs(10),
+    wall_time: Duration::from_secs(20),
+    memory: 1 << 30,
+    output: 1 << 20,
+    // checkers may be shell scripts that run other programs
+    processes: 16,
+    address_space: true,
+};
+const ENV: &[(&str, &str)] = &[("PATH", "/usr/bin:/bin")];
+
+pub struct Check {
+    pub verdict: Verdict,
+    pub comment: Box<str>,
+    pub points: Option<f64>,
+}
+
+impl Check {
+    fn new(verdict: Verdict, comment: Box<str>) -> Self {
+        Self { verdict, comment, points: None }
+    }
+}
+
+pub fn comment(bytes: &[u8]) -> Box<str> {
+    truncate(bytes, COMMENT_LIMIT)
+}
+
+// limit is in bytes, the text is cut on a char boundary
+pub fn truncate(bytes: &[u8], limit: usize) -> Box<str> {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim();
+    if text.len() <= limit {
+        return text.into();
+    }
+    let end = (0..=limit).rev().find(|i| text.is_char_boundary(*i)).unwrap_or(0);
+    format!("{}...", &text[..end]).into()
+}
+
+// testlib writes "points <value> <message>"
+fn parse_points(comment: &str) -> Option<f64> {
+    let comment = comment.strip_prefix("points").unwrap_or(comment);
+    comment.split_whitespace().next()?.parse().ok()
+}
+
+// checker <input> <output> <answer>, the verdict is the exit code and the message is on stderr,
+// it is sandboxed and sees only its arguments, the message is kept in the log file
+pub async fn testlib(checker: &Path, input: &Path, output: &Path, answer: &Path, log: &Path) -> Check {
+    let run = || -> std::io::Result<sandbox::Run> {
+        Ok(sandbox::Run {
+            program: checker,
+            args: vec![input.as_os_str(), output.as_os_str(), answer.as_os_str()],
+            stdin: Stdio::null(),
+            stdout: Stdio::null(),
+            stderr: std::fs::File::create(log)?.into(),
+            limits: &LIMITS,
+            mounts: [checker, input, output, answer].into_iter().map(sandbox::Mount::read_only).collect(),
+            env: ENV,
+        })
+    };
+    let outcome = match run() {
+        Ok(run) => sandbox::run(run, Path::new("/tmp")).await,
+        Err(e) => Err(e),
+    };
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            log::error!("can't run checker {}: {e}", checker.display());
+            return Check::new(Verdict::Fail, "can't run checker".into());
+        }
+    };
+    if outcome.time_exceeded(&LIMITS) || outcome.idleness_exceeded() {
+        log::error!("checker {} took more than {} s", checker.display(), LIMITS.cpu_time.as_secs());
+        return Check::new(Verdict::Fail, "checker took too long".into());
+    }
+    testlib_exit(outcome.status.code(), comment(&tokio::fs::read(log).await.unwrap_or_default()))
+}
+
+pub fn testlib_exit(code: Option<i32>, comment: Box<str>) -> Check {
+    match code {
+        Some(OK) => Check::new(Verdict::Ok, comment),
+        Some(WA) => Check::new(Verdict::Wa, comment),
+        Some(PE | DIRT | UNEXPECTED_EOF) => Check::new(Verdict::Pe, comment),
+        Some(POINTS) => match parse_points(&comment) {
+            Some(points) if (0.0..=1.0).contains(&points) => Check {
+                verdict: if points >= 1.0 {Verdict::Ok} else {Verdict::Wa},
+                comment,
+                points: Some(points),
+            },
+            // points are a share of the test points, the checker doesn't know how many the test has
+            Some(points) => {
+                log::error!("checker gave points {points} out of 0..=1: {comment}");
+                Check::new(Verdict::Fail, comment)
+            }
+            None => {
+                log::error!("checker gave points without a value: {comment}");
+                Check::new(Verdict::Fail, comment)
+            }
+        },
+        Some(FAIL) => {
+            log::error!("checker failed: {comment}");
+            Check::new(Verdict::Fail, comment)
+        }
+        code => {
+            log::error!("checker exited with unknown code {code:?}: {comment}");
+            Check::new(Verdict::Fail, comment)
+        }
+    }
+}
+
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{n}{suffix}")
+}
+
+fn compare_sequences<'a>(name: &str, output: Vec<&'a str>, answer: Vec<&'a str>, equal: impl Fn(&str, &str) -> Result<bool, Box<str>>) -> Check {
+    for (i, (o, a)) in output.iter().zip(&answer).enumerate() {
+        match equal(o, a) {
+            Ok(true) => {}
+            Ok(false) => return Check::new(Verdict::Wa, comment(format!("{} {name} differ - expected: '{a}', found: '{o}'", ordinal(i + 1)).as_bytes())),
+            Err(e) => return Check::new(Verdict::Pe, e),
+        }
+    }
+    if output.len() != answer.len() {
+        return Check::new(Verdict::Wa, format!("expected {} {name}, found {}", answer.len(), output.len()).into());
+    }
+    Check::new(Verdict::Ok, format!("{} {name}", answer.len()).into())
+}
+
+fn float_equal(abs_eps: f64, rel_eps: f64) -> impl Fn(&str, &str) -> Result<bool, Box<str>> {
+    move |o, a| {
+        let a_value = if let Ok(v) = a.parse::<f64>() {v} else {
+            return Ok(o == a);
+        };
+        let o_value = o.parse::<f64>().map_err(|_| comment(format!("expected a number, found: '{o}'").as_bytes()))?;
+        let diff = (o_value - a_value).abs();
+        Ok(diff <= abs_eps || diff <= rel_eps * a_value.abs())
+    }
+}
+
+fn tokens(s: &str) -> Vec<&str> {
+    s.split_ascii_whitespace().collect()
+}
+
+fn lines(s: &str) -> Vec<&str> {
+    let mut lines: Vec<&str> = s.lines().map(|l| l.trim_end()).collect();
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+pub fn builtin(checker: &Checker, output: &[u8], answer: &[u8]) -> Check {
+    let (output, answer) = (String::from_utf8_lossy(output), String::from_utf8_lossy(answer));
+    match checker {
+        Checker::Exact => if output == answer {
+            Check::new(Verdict::Ok, "".into())
+        } else {
+            let i = output.bytes().zip(answer.bytes()).take_while(|(o, a)| o == a).count();
+            Check::new(Verdict::Wa, format!("output differs from answer at byte {i}").into())
+        },
+        Checker::Tokens => compare_sequences("tokens", tokens(&output), tokens(&answer), |o, a| Ok(o == a)),
+        Checker::Lines => compare_sequences("lines", lines(&output), lines(&answer), |o, a| Ok(o == a)),
+        Checker::Float { abs_eps, rel_eps } => compare_sequences("tokens", tokens(&output), tokens(&answer), float_equal(*abs_eps, *rel_eps)),
+        Checker::External => Check::new(Verdict::Fail, "external checker is not built-in".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn testlib_verdicts() {
+        assert_eq!(testlib_exit(Some(OK), "ok".into()).verdict, Verdict::Ok);
+        assert_eq!(testlib_exit(Some(WA), "wrong".into()).verdict, Verdict::Wa);
+        for code in [PE, DIRT, UNEXPECTED_EOF] {
+            assert_eq!(testlib_exit(Some(code), "".into()).verdict, Verdict::Pe);
+        }
+        assert_eq!(testlib_exit(Some(FAIL), "".into()).verdict, Verdict::Fail);
+        assert_eq!(testlib_exit(Some(5), "".into()).verdict, Verdict::Fail);
+        // killed by a signal
+        assert_eq!(testlib_exit(None, "".into()).verdict, Verdict::Fail);
+    }
+
+    #[test]
+    fn testlib_points() {
+        let check = testlib_exit(Some(POINTS), "points 0.25 partial".into());
+        assert_eq!((check.verdict, check.points), (Verdict::Wa, Some(0.25)));
+        assert_eq!(&*check.comment, "points 0.25 partial");
+        let check = testlib_exit(Some(POINTS), "1".into());
+        assert_eq!((check.verdict, check.points), (Verdict::Ok, Some(1.0)));
+        let check = testlib_exit(Some(POINTS), "points 0".into());
+        assert_eq!((check.verdict, check.points), (Verdict::Wa, Some(0.0)));
+        // a share out of range is a checker failure, not a verdict for the solution
+        for comment in ["points 3", "points -1", "points 1.01", "points NaN"] {
+            let check = testlib_exit(Some(POINTS), comment.into());
+            assert_eq!((check.verdict, check.points), (Verdict::Fail, None));
+        }
+        let check = testlib_exit(Some(POINTS), "points".into());
+        assert_eq!((check.verdict, check.points), (Verdict::Fail, None));
+    }
+
+    fn verdict(checker: &Checker, output: &str, answer: &str) -> Verdict {
+        builtin(checker, output.as_bytes(), answer.as_bytes()).verdict
+    }
+
+    #[test]
+    fn exact_tokens_lines() {
+        assert_eq!(verdict(&Checker::Exact, "1 2\n", "1 2\n"), Verdict::Ok);
+        assert_eq!(verdict(&Checker::Exact, "1 2", "1 2\n"), Verdict::Wa);
+        assert_eq!(verdict(&Checker::Tokens, " 1\n\n2  ", "1 2\n"), Verdict::Ok);
+        assert_eq!(verdict(&Checker::Tokens, "1 2 3", "1 2"), Verdict::Wa);
+        assert_eq!(verdict(&Checker::Lines, "a b  \nc\n\n\n", "a b\nc\n"), Verdict::Ok);
+        assert_eq!(verdict(&Checker::Lines, "a  b\nc\n", "a b\nc\n"), Verdict::Wa);
+        assert_eq!(&*builtin(&Checker::Tokens, b"1 3", b"1 2").comment, "2nd tokens differ - expected: '2', found: '3'");
+    }
+
+    #[test]
+    fn float_eps() {
+        let checker = Checker::Float { abs_eps: 1e-3, rel_eps: 1e-6 };
+        assert_eq!(verdict(&checker, "0.1004", "0.1"), Verdict::Ok);
+        assert_eq!(verdict(&checker, "0.102", "0.1"), Verdict::Wa);
+        // large values pass by the relative error
+        assert_eq!(verdict(&checker, "1000000.5", "1000000"), Verdict::Ok);
+        assert_eq!(verdict(&checker, "1000002", "1000000"), Verdict::Wa);
+
+        let equal = float_equal(0.0, 0.0);
+        assert_eq!(equal("1e2", "100"), Ok(true));
+        assert_eq!(equal("100.5", "100"), Ok(false));
+    }
+
+    #[test]
+    fn float_non_numeric() {
+        let checker = Checker::Float { abs_eps: 1e-6, rel_eps: 1e-6 };
+        // a word in the answer is compared as is
+        assert_eq!(verdict(&checker, "YES 1.0", "YES 1"), Verdict::Ok);
+        assert_eq!(verdict(&checker, "NO 1", "YES 1"), Verdict::Wa);
+        // a word instead of a number is a presentation error
+        let check = builtin(&checker, b"abc", b"1.5");
+        assert_eq!(check.verdict, Verdict::Pe);
+        assert_eq!(&*check.comment, "expected a number, found: 'abc'");
+    }
+}
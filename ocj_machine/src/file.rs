@@ -1,11 +1,37 @@
-use std::path::Path;
+use std::{os::unix::fs::PermissionsExt, path::Path};
 use tokio::{fs::{self, File}, io::BufReader};
 use tokio_tar::Archive;
 
-use crate::{config::file as config, judge};
+use crate::{config::{auth::Token, file as config, machine}, judge, sandbox};
+
+// the machine keeps its id between connections and restarts
+pub const ID: &str = "machine_id";
+// issued by an admin with machines.token
+pub const TOKEN: &str = "machine_token";
 
 pub async fn init() {
     _ = fs::create_dir(judge::DIR).await;
+    _ = fs::create_dir(sandbox::JAIL).await;
+}
+
+pub async fn load_id() -> Result<machine::Id, ()> {
+    match fs::read_to_string(ID).await {
+        Ok(id) => id.trim().parse().map_err(|_| log::error!("can't parse machine id from {ID}")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let id = rand::random::<machine::Id>();
+            fs::write(ID, id.to_string()).await.map_err(|e| log::error!("can't write machine id to {ID}: {e}"))?;
+            Ok(id)
+        }
+        Err(e) => {
+            log::error!("can't read machine id from {ID}: {e}");
+            Err(())
+        }
+    }
+}
+
+pub async fn load_token() -> Result<Token, ()> {
+    let token = fs::read_to_string(TOKEN).await.map_err(|e| log::error!("can't read machine token from {TOKEN}: {e}"))?;
+    token.trim().parse().map_err(|_| log::error!("can't parse machine token from {TOKEN}"))
 }
 
 pub async fn decompress(dir_path: &Path) -> Result<(), ()> {
@@ -20,7 +46,11 @@ pub async fn decompress(dir_path: &Path) -> Result<(), ()> {
 
     let mut archive = Archive::new(dec);
 
-    archive.unpack("tests").await.unwrap(); 
+    // binaries compiled for the previous tests must not survive
+    _ = fs::remove_dir_all(config::TESTS).await;
+    archive.unpack(config::TESTS).await.unwrap();
+    // only the machine user reads the answers
+    fs::set_permissions(config::TESTS, std::fs::Permissions::from_mode(0o700)).await.unwrap();
     Ok(())
 }
 
@@ -0,0 +1,42 @@
+use std::{ffi::CString, path::Path};
+
+use ocj_config::{machine::Health, solution};
+
+const MB: u64 = 1024 * 1024;
+
+fn load() -> f64 {
+    let mut load = [0.0; 3];
+    if unsafe { libc::getloadavg(load.as_mut_ptr(), 3) } < 1 {
+        return 0.0;
+    }
+    load[0]
+}
+
+fn free_disk(path: &Path) -> u64 {
+    let path = if let Ok(p) = CString::new(path.as_os_str().as_encoded_bytes()) {p} else {
+        return 0;
+    };
+    let mut stat = unsafe { std::mem::zeroed::<libc::statvfs>() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } < 0 {
+        return 0;
+    }
+    stat.f_bavail as u64 * stat.f_frsize as u64 / MB
+}
+
+// page cache can be reclaimed, so MemAvailable is what is free for solutions
+async fn free_memory() -> u64 {
+    let meminfo = tokio::fs::read_to_string("/proc/meminfo").await.unwrap_or_default();
+    meminfo.lines()
+        .find_map(|l| l.strip_prefix("MemAvailable:"))
+        .and_then(|kb| kb.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map_or(0, |kb| kb * 1024 / MB)
+}
+
+pub async fn health(judging: Vec<solution::Id>) -> Health {
+    Health {
+        load: load(),
+        free_disk: free_disk(Path::new(".")),
+        free_memory: free_memory().await,
+        judging,
+    }
+}
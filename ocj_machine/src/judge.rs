@@ -1,34 +1,435 @@
-use crate::config::solution::{Solution, Lang};
-use ocj_config::{self as config, solution::Verdict, tests::Config};
-use tokio::{fs::{self, File}, io::AsyncWriteExt, process::Command, task::JoinHandle};
+use std::{ffi::{OsStr, OsString}, io, os::unix::{fs::PermissionsExt, process::ExitStatusExt}, path::{Path, PathBuf}, process::Stdio, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
+
+use crate::{config::solution::Solution, checker, lang::{self, Registry}, sandbox};
+use ocj_config::{self as config, solution::{GroupResult, Progress, TestResult, Verdict}, tests::{Checker, Config, Judging, Policy, CHECKER}};
+use tokio::{fs, sync::{mpsc::unbounded_channel, Semaphore}, task::JoinHandle};
 
 pub const DIR: &str = "solutions";
+pub const COMPILE_TIME_LIMIT: Duration = Duration::from_secs(30);
+// compilers run sandboxed like solutions, with room for their helper processes
+const COMPILE_LIMITS: sandbox::Limits = sandbox::Limits {
+    cpu_time: COMPILE_TIME_LIMIT,
+    wall_time: COMPILE_TIME_LIMIT,
+    memory: 2 << 30,
+    output: 256 << 20,
+    processes: 64,
+    address_space: true,
+};
+// compilers look for their helpers and caches in the environment
+const COMPILE_ENV: &[(&str, &str)] = &[("PATH", "/usr/local/bin:/usr/bin:/bin"), ("HOME", "/tmp")];
+// default size of the compiler output and runtime error stderr kept in a result, in bytes
+pub const LOG_LIMIT: usize = 4096;
 
-pub async fn judge(solution: Solution) -> Result<Verdict, ()> {
-    _ = fs::create_dir(format!("{DIR}/{}", solution.id)).await;
-    let file_path = format!("{DIR}/{}/solution.{}", solution.id, solution.lang.file_ext());
+pub struct Report {
+    pub verdict: Verdict,
+    // maxima over all tests
+    pub time: Duration,
+    pub memory: u64,
+    pub tests: Vec<TestResult>,
+    pub failed_test: Option<u16>,
+    pub score: u32,
+    pub groups: Vec<GroupResult>,
+    pub compile_output: Option<Box<str>>,
+}
+
+impl Report {
+    fn verdict(verdict: Verdict) -> Self {
+        Self { verdict, time: Duration::ZERO, memory: 0, tests: Vec::new(), failed_test: None, score: 0, groups: Vec::new(), compile_output: None }
+    }
+}
+
+fn score(config: &Config, tests: &[TestResult]) -> (u32, Vec<GroupResult>) {
+    // share of the test points: the checker may give a part of them
+    let share = |n: u16| tests.iter().find(|t| t.test_number == n).map_or(0.0, |t| match t.points {
+        Some(p) => p,
+        None if t.verdict == Verdict::Ok => 1.0,
+        None => 0.0,
+    });
+    if config.groups.is_empty() {
+        return (if tests.iter().all(|t| t.verdict == Verdict::Ok) {100} else {0}, Vec::new());
+    }
+
+    let mut results: Vec<GroupResult> = Vec::with_capacity(config.groups.len());
+    for (number, group) in config.groups.iter().enumerate() {
+        let skipped = group.dependencies.iter().any(|d| !results[*d as usize].passed);
+        let count = (group.last_test - group.first_test + 1) as u32;
+        let shares: Vec<f64> = (group.first_test..=group.last_test).map(share).collect();
+        let passed_count = shares.iter().filter(|s| **s >= 1.0).count() as u32;
+        let points = match group.policy {
+            _ if skipped => 0,
+            Policy::Complete if passed_count == count => group.points,
+            Policy::Complete => 0,
+            Policy::EachTest => (group.points as f64 * shares.iter().sum::<f64>() / count as f64).floor() as u32,
+        };
+        results.push(GroupResult {
+            group: number as u16,
+            points,
+            passed: !skipped && passed_count == count,
+            skipped,
+        });
+    }
+    (results.iter().map(|g| g.points).sum(), results)
+}
+
+
+struct Problem {
+    dir: PathBuf,
+    // the solution limits, scaled for its language
+    limits: sandbox::Limits,
+    interactor_limits: sandbox::Limits,
+    checker: Checker,
+    interactor: Option<PathBuf>,
+    log_limit: usize,
+    judging: Judging,
+    // the language runtime outside the usual system directories
+    runtime: Vec<PathBuf>,
+}
+
+impl Problem {
+    // a run sees only its solution directory and the runtime
+    fn mounts<'a>(&'a self, solution_dir: &'a Path) -> Vec<sandbox::Mount<'a>> {
+        let mut mounts = vec![sandbox::Mount::read_only(solution_dir)];
+        mounts.extend(self.runtime.iter().map(|p| sandbox::Mount::read_only(p)));
+        mounts
+    }
+}
 
-    let file = File::create(&file_path).await;
-    let mut file = if let Ok(file) = file {
-        file
+impl Problem {
+    async fn new(dir: PathBuf, config: &Config, lang: &lang::Lang, log_limit: usize) -> Self {
+        let checker = match &config.checker {
+            Some(c) => c.clone(),
+            None if fs::try_exists(dir.join(CHECKER)).await.unwrap_or(false) => Checker::External,
+            None if fs::try_exists(dir.join(format!("{CHECKER}.cpp"))).await.unwrap_or(false) => Checker::External,
+            None => Checker::Tokens,
+        };
+        let base = sandbox::Limits::from(config);
+        let limits = base.for_lang(lang);
+        Self {
+            interactor_limits: sandbox::Limits {
+                cpu_time: limits.wall_time,
+                wall_time: limits.wall_time,
+                ..base
+            },
+            limits,
+            checker,
+            interactor: config.interactor.as_ref().map(|i| dir.join(i.as_ref())),
+            log_limit,
+            judging: config.judging(),
+            runtime: lang.mounts.clone(),
+            dir,
+        }
+    }
+}
+
+struct Program {
+    path: PathBuf,
+    args: Vec<OsString>,
+}
+
+impl Program {
+    fn args(&self) -> Vec<&OsStr> {
+        self.args.iter().map(|a| a.as_os_str()).collect()
+    }
+}
+
+fn no_run_result(test_number: u16, verdict: Verdict, comment: &str) -> TestResult {
+    TestResult {
+        test_number,
+        verdict,
+        time: 0,
+        memory: 0,
+        comment: comment.into(),
+        exit_code: None,
+        signal: None,
+        points: None,
+        stderr: None,
+    }
+}
+
+fn test_result(test_number: u16, outcome: &sandbox::Outcome, limits: &sandbox::Limits, check: checker::Check) -> TestResult {
+    TestResult {
+        test_number,
+        verdict: check.verdict,
+        time: outcome.cpu_time.min(limits.cpu_time).as_millis() as u64,
+        memory: outcome.peak_memory.min(limits.memory) / 1024,
+        comment: check.comment,
+        exit_code: outcome.status.code(),
+        signal: outcome.status.signal(),
+        points: check.points,
+        stderr: None,
+    }
+}
+
+// the solution stderr is kept only for runtime errors
+async fn runtime_error(mut result: TestResult, stderr_path: &Path, problem: &Problem) -> io::Result<TestResult> {
+    result.stderr = Some(checker::truncate(&fs::read(stderr_path).await?, problem.log_limit));
+    Ok(result)
+}
+
+fn limits_verdict(outcome: &sandbox::Outcome, limits: &sandbox::Limits) -> Option<Verdict> {
+    if outcome.security_violated() {
+        Some(Verdict::Sv)
+    } else if outcome.time_exceeded(limits) {
+        Some(Verdict::Tl)
+    } else if outcome.idleness_exceeded() {
+        Some(Verdict::Il)
+    } else if outcome.memory_exceeded(limits) {
+        Some(Verdict::Ml)
+    } else if outcome.output_exceeded() {
+        Some(Verdict::Ol)
     } else {
-        log::error!("can't create file");
-        return Err(());
+        None
+    }
+}
+
+async fn run_batch(program: &Program, sandbox_dir: &Path, solution_dir: &Path, problem: &Problem, test_number: u16) -> io::Result<TestResult> {
+    let input = problem.dir.join(format!("{test_number}.in"));
+    let output = solution_dir.join(format!("{test_number}.out"));
+    let answer = problem.dir.join(format!("{test_number}.out"));
+    let stderr_path = solution_dir.join(format!("{test_number}.err"));
+
+    let outcome = sandbox::run(sandbox::Run {
+        program: &program.path,
+        args: program.args(),
+        stdin: std::fs::File::open(&input)?.into(),
+        stdout: std::fs::File::create(&output)?.into(),
+        stderr: std::fs::File::create(&stderr_path)?.into(),
+        limits: &problem.limits,
+        mounts: problem.mounts(solution_dir),
+        env: &[],
+    }, sandbox_dir).await?;
+    let result = |verdict| test_result(test_number, &outcome, &problem.limits, checker::Check { verdict, comment: "".into(), points: None });
+    if let Some(verdict) = limits_verdict(&outcome, &problem.limits) {
+        return Ok(result(verdict));
+    }
+    if !outcome.status.success() {
+        return runtime_error(result(Verdict::Re), &stderr_path, problem).await;
+    }
+
+    let check = if let Checker::External = problem.checker {
+        checker::testlib(&problem.dir.join(CHECKER), &input, &output, &answer, &solution_dir.join(format!("{test_number}.checker"))).await
+    } else {
+        checker::builtin(&problem.checker, &fs::read(&output).await?, &fs::read(&answer).await?)
     };
+    Ok(test_result(test_number, &outcome, &problem.limits, check))
+}
+
+// testlib interactor: interactor <input> <output>, its stdin and stdout are the solution's stdout and stdin
+async fn run_interactive(program: &Program, sandbox_dir: &Path, solution_dir: &Path, problem: &Problem, test_number: u16) -> io::Result<TestResult> {
+    let interactor = problem.interactor.as_ref().unwrap();
+    let input = problem.dir.join(format!("{test_number}.in"));
+    let stderr_path = solution_dir.join(format!("{test_number}.interactor"));
+    let solution_stderr_path = solution_dir.join(format!("{test_number}.err"));
+    let (solution_stdin, interactor_stdout) = sandbox::pipe()?;
+    let (interactor_stdin, solution_stdout) = sandbox::pipe()?;
+
+    let (outcome, interactor_outcome) = sandbox::run_interactive(sandbox::Run {
+        program: &program.path,
+        args: program.args(),
+        stdin: solution_stdin,
+        stdout: solution_stdout,
+        stderr: std::fs::File::create(&solution_stderr_path)?.into(),
+        limits: &problem.limits,
+        mounts: problem.mounts(solution_dir),
+        env: &[],
+    }, sandbox::Run {
+        program: interactor,
+        // nothing checks the interactor output
+        args: vec![input.as_os_str(), OsStr::new("/dev/null")],
+        stdin: interactor_stdin,
+        stdout: interactor_stdout,
+        stderr: std::fs::File::create(&stderr_path)?.into(),
+        limits: &problem.interactor_limits,
+        mounts: vec![sandbox::Mount::read_only(solution_dir), sandbox::Mount::read_only(interactor), sandbox::Mount::read_only(&input)],
+        env: &[],
+    }, sandbox_dir).await?;
+
+    let comment = checker::comment(&fs::read(&stderr_path).await?);
+    let result = |verdict, comment| test_result(test_number, &outcome, &problem.limits, checker::Check { verdict, comment, points: None });
+    if let Some(verdict) = limits_verdict(&outcome, &problem.limits) {
+        return Ok(result(verdict, "".into()));
+    }
+    // both are waiting for each other, the solution is to blame
+    if interactor_outcome.wall_time_exceeded {
+        return Ok(result(Verdict::Il, comment));
+    }
+    if interactor_outcome.status.code().is_none() {
+        log::error!("interactor did not finish: {:?}", interactor_outcome.status);
+        return Ok(result(Verdict::Fail, comment));
+    }
+    // a wrong answer makes the interactor stop, and the solution may die on a closed pipe after that
+    let check = checker::testlib_exit(interactor_outcome.status.code(), comment);
+    if check.verdict == Verdict::Ok && !outcome.status.success() {
+        return runtime_error(result(Verdict::Re, check.comment), &solution_stderr_path, problem).await;
+    }
+    Ok(test_result(test_number, &outcome, &problem.limits, check))
+}
+
+// the error is the compiler output, the compiler sees and writes only the solution directory
+async fn compile(lang: &lang::Lang, command: &[String], dir: &Path, log_limit: usize) -> Result<(), Box<str>> {
+    let Some((program, args)) = command.split_first() else {
+        return Ok(());
+    };
+    let limits = sandbox::Limits { address_space: lang.limit_address_space, ..COMPILE_LIMITS };
+    let log_path = dir.join("compile.log");
+    let run = || -> io::Result<sandbox::Run> {
+        // some compilers write errors to stdout
+        let log = std::fs::File::create(&log_path)?;
+        let mut mounts = vec![sandbox::Mount::writable(dir)];
+        mounts.extend(lang.mounts.iter().map(|p| sandbox::Mount::read_only(p)));
+        Ok(sandbox::Run {
+            program: Path::new(program),
+            args: args.iter().map(OsStr::new).collect(),
+            stdin: Stdio::null(),
+            stdout: log.try_clone()?.into(),
+            stderr: log.into(),
+            limits: &limits,
+            mounts,
+            env: COMPILE_ENV,
+        })
+    };
+    let outcome = match run() {
+        Ok(run) => sandbox::run(run, dir).await,
+        Err(e) => Err(e),
+    };
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            log::error!("can't run compiler {program}: {e}");
+            return Err(format!("can't run compiler {program}").into());
+        }
+    };
+    if outcome.time_exceeded(&limits) || outcome.idleness_exceeded() {
+        return Err(format!("compilation took more than {} s", COMPILE_TIME_LIMIT.as_secs()).into());
+    }
+    if outcome.memory_exceeded(&limits) {
+        return Err(format!("compiler used more than {} MB", limits.memory >> 20).into());
+    }
+    if outcome.status.success() {
+        return Ok(());
+    }
+    // paths on the machine are of no use to contestants
+    let output = String::from_utf8_lossy(&fs::read(&log_path).await.unwrap_or_default()).replace(&format!("{}/", dir.display()), "");
+    Err(checker::truncate(output.as_bytes(), log_limit))
+}
+
+// the compile stage, the error is a compilation error
+async fn build(lang: &lang::Lang, solution_dir: &Path, source: &Path, memory: u64, log_limit: usize) -> Result<Program, Box<str>> {
+    let binary = solution_dir.join("solution");
+    if let Some(command) = &lang.compile {
+        compile(lang, &lang::Lang::expand(command, solution_dir, source, &binary, memory), solution_dir, log_limit).await?;
+    }
+    // the registry makes sure the run command is not empty
+    let mut run = lang::Lang::expand(&lang.run, solution_dir, source, &binary, memory).into_iter().map(OsString::from);
+    Ok(Program { path: run.next().unwrap_or_default().into(), args: run.collect() })
+}
+
+// the run stage, tests run with bounded concurrency and progress is reported by the first unfinished one
+async fn run_tests(program: Arc<Program>, sandbox_dir: Arc<PathBuf>, solution_dir: &Path, problem: Arc<Problem>, test_count: u16, concurrency: usize, progress: &impl Fn(Progress)) -> Report {
+    let (sender, mut receiver) = unbounded_channel::<JoinHandle<TestResult>>();
+    // set by a failed test when judging stops on failure, tests started before it still finish
+    let stop = Arc::new(AtomicBool::new(false));
+
+    // tests start in order, each once a permit is free
+    let solution_dir = solution_dir.to_path_buf();
+    let judging = problem.judging;
+    let starter = tokio::spawn(async move {
+        let semaphore = Arc::new(Semaphore::new(concurrency));
+        for test_number in 1..=test_count {
+            let permit = semaphore.clone().acquire_owned().await.unwrap();
+            if stop.load(Ordering::Acquire) {
+                break;
+            }
+            let (program, sandbox_dir, solution_dir, problem, stop) = (program.clone(), sandbox_dir.clone(), solution_dir.clone(), problem.clone(), stop.clone());
+            _ = sender.send(tokio::spawn(async move {
+                let result = if problem.interactor.is_some() {
+                    run_interactive(&program, &sandbox_dir, &solution_dir, &problem, test_number).await
+                } else {
+                    run_batch(&program, &sandbox_dir, &solution_dir, &problem, test_number).await
+                };
+                let result = result.unwrap_or_else(|e| {
+                    log::error!("can't run test {test_number}: {e}");
+                    no_run_result(test_number, Verdict::Fail, "can't run solution")
+                });
+                if result.verdict != Verdict::Ok && problem.judging == Judging::StopOnFailure {
+                    stop.store(true, Ordering::Release);
+                }
+                drop(permit);
+                result
+            }));
+        }
+    });
 
-    file.write(solution.code.as_bytes()).await.unwrap();
+    let mut res = Report::verdict(Verdict::Ok);
 
-    let mut command = match solution.lang {
-        Lang::Cpp => {
-            let mut c = Command::new("g++");
-            c.args(&[
-                format!("{file_path}"),
-                "-o".to_string(), format!("{DIR}/{}/solution", solution.id),
-            ]);
-            c
+    let mut test_number = 0;
+    while let Some(handle) = receiver.recv().await {
+        test_number += 1;
+        let mut test = handle.await.unwrap_or_else(|e| {
+            log::error!("test {test_number} panicked: {e}");
+            no_run_result(test_number, Verdict::Fail, "machine error")
+        });
+        // tests running alongside the failed one are not shown either
+        if res.failed_test.is_some() && judging == Judging::StopOnFailure {
+            test = no_run_result(test.test_number, Verdict::Skipped, "");
+        }
+        if test.test_number < test_count {
+            progress(Progress::Running { test: test.test_number + 1, test_count });
+        }
+        if Verdict::Ok != test.verdict && res.verdict == Verdict::Ok {
+            res.verdict = test.verdict.clone();
+            res.failed_test = Some(test.test_number);
         }
+        res.time = res.time.max(Duration::from_millis(test.time));
+        res.memory = res.memory.max(test.memory * 1024);
+        res.tests.push(test);
+    }
+    _ = starter.await;
+    for test_number in res.tests.len() as u16 + 1..=test_count {
+        res.tests.push(no_run_result(test_number, Verdict::Skipped, ""));
+    }
+    res
+}
+
+// a failure of the machine is reported as the Fail verdict, so every solution gets a result
+pub async fn judge(solution: Solution, registry: &Registry, log_limit: usize, test_concurrency: usize, progress: impl Fn(Progress)) -> Report {
+    let solution_dir = format!("{DIR}/{}", solution.id);
+    // left by an earlier attempt of the same solution
+    _ = fs::remove_dir_all(&solution_dir).await;
+    let res = judge_solution(solution, registry, log_limit, test_concurrency, progress).await.unwrap_or_else(|()| Report::verdict(Verdict::Fail));
+    if let Err(e) = fs::remove_dir_all(&solution_dir).await {
+        log::error!("can't remove solution directory {solution_dir}: {e}");
+    }
+    log::debug!("{:?} on test {:?}, {} points, {} ms {} KB", res.verdict, res.failed_test, res.score, res.time.as_millis(), res.memory / 1024);
+    res
+}
+
+async fn judge_solution(solution: Solution, registry: &Registry, log_limit: usize, test_concurrency: usize, progress: impl Fn(Progress)) -> Result<Report, ()> {
+    let lang = if let Some(lang) = registry.get(&solution.lang) {
+        lang
+    } else {
+        log::error!("language {} is not supported by the machine", solution.lang.0);
+        return Err(());
     };
 
+    _ = fs::create_dir(format!("{DIR}/{}", solution.id)).await;
+    let solution_dir = if let Ok(dir) = fs::canonicalize(format!("{DIR}/{}", solution.id)).await {
+        dir
+    } else {
+        log::error!("can't create solution directory");
+        return Err(());
+    };
+    // the compiler writes there under its own uid, no other run sees the directory
+    if fs::set_permissions(&solution_dir, std::fs::Permissions::from_mode(0o777)).await.is_err() {
+        log::error!("can't make solution directory writable");
+        return Err(());
+    }
+    let source = solution_dir.join(lang.source.as_ref());
+    if fs::write(&source, solution.code.as_bytes()).await.is_err() {
+        log::error!("can't create file");
+        return Err(());
+    }
+
     let tests_dir = format!("{}/{}/", config::file::TESTS, solution.problem_number);
 
     let problem_config = if let Ok(conf) = serde_json::from_str::<Config>(
@@ -44,86 +445,103 @@ pub async fn judge(solution: Solution) -> Result<Verdict, ()> {
         log::error!("cannot parse config file for {} problem", solution.problem_number);
         return Err(());
     };
+    if let Err(e) = problem_config.check_groups() {
+        log::error!("incorrect config file for {} problem: {e}", solution.problem_number);
+        return Err(());
+    }
 
-    
-
-    let status = command.status().await;
-    let status = if let Ok(status) = status {
-        status
-    } else {
-        return Ok(Verdict::Ce);
+    progress(Progress::Compiling);
+    let memory = sandbox::Limits::from(&problem_config).for_lang(lang).memory;
+    let program = match build(lang, &solution_dir, &source, memory, log_limit).await {
+        Ok(program) => Arc::new(program),
+        Err(output) => return Ok(Report { compile_output: Some(output), ..Report::verdict(Verdict::Ce) }),
     };
 
-    if !status.success() {
-        return Ok(Verdict::Ce);
+    let sandbox_dir = Arc::new(PathBuf::from(format!("{DIR}/{}/sandbox", solution.id)));
+    if fs::create_dir(sandbox_dir.as_ref()).await.is_err() {
+        log::error!("can't create sandbox directory");
+        return Err(());
     }
+    let problem = if let Ok(dir) = fs::canonicalize(&tests_dir).await {
+        Arc::new(Problem::new(dir, &problem_config, lang, log_limit).await)
+    } else {
+        log::error!("can't find tests directory for {} problem", solution.problem_number);
+        return Err(());
+    };
 
-    let mut tasks = Vec::<JoinHandle<Verdict>>::new();
+    progress(Progress::Running { test: 1, test_count: problem_config.test_count });
+    let mut res = run_tests(program, sandbox_dir, &solution_dir, problem, problem_config.test_count, test_concurrency, &progress).await;
 
-    for test_number in 1..=(problem_config.test_count) {
-        tasks.push(tokio::spawn(async move {
-            let mut command = Command::new(format!("{DIR}/{}/solution", solution.id));
-            command.stdin(std::fs::File::open(format!("{}/{}/{test_number}.in", config::file::TESTS, solution.problem_number)).unwrap());
-            command.stdout(std::fs::File::create(format!("{DIR}/{}/{test_number}.out", solution.id)).unwrap());
+    progress(Progress::Checking);
+    (res.score, res.groups) = score(&problem_config, &res.tests);
+    Ok(res)
+}
 
-            let status = command.status().await;
-            if let Ok(status) = status {
-                if !status.success() {
-                    return Verdict::Re;
-                }
-            } else {
-                return Verdict::Re;
-            };
-
-            command = Command::new(format!("{}/{}/checker", config::file::TESTS, solution.problem_number));
-            command.arg(format!("{}/{}/{test_number}.out", config::file::TESTS, solution.problem_number));
-            command.arg(format!("{DIR}/{}/{test_number}.out", solution.id));
-
-            let output = command.output().await;
-            if let Ok(output) = output {
-                let v = if let Ok(v) = String::from_utf8(output.stdout) {
-                    v
-                } else {
-                    log::error!("incorrect checker verdict");
-                    return Verdict::Pe;
-                };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                match v.as_str() {
-                    "Ok" => {
-                        Verdict::Ok
-                    },
+    fn config(groups: &str) -> Config {
+        serde_json::from_str(&format!(r#"{{"test_count": 8, "groups": {groups}}}"#)).unwrap()
+    }
 
-                    "Wa" => {
-                        Verdict::Wa
-                    },
+    fn results(verdicts: &[Verdict]) -> Vec<TestResult> {
+        verdicts.iter().enumerate().map(|(i, v)| TestResult {
+            test_number: i as u16 + 1,
+            verdict: v.clone(),
+            time: 0,
+            memory: 0,
+            comment: "".into(),
+            exit_code: None,
+            signal: None,
+            points: None,
+            stderr: None,
+        }).collect()
+    }
 
-                    "Pe" => {
-                        Verdict::Pe
-                    },
+    #[test]
+    fn all_or_nothing_without_groups() {
+        let config = config("[]");
+        assert_eq!(score(&config, &results(&[Verdict::Ok, Verdict::Ok])).0, 100);
+        assert_eq!(score(&config, &results(&[Verdict::Ok, Verdict::Wa])).0, 0);
+    }
 
-                    _ => {
-                        log::error!("incorrect checker verdict");
-                        Verdict::Pe
-                    },
-                }
-            } else {
-                log::error!("checker output incorrect");
-                Verdict::Pe
-            }
-        }));
+    #[test]
+    fn failed_dependency_skips_group() {
+        let config = config(r#"[
+            {"first_test": 1, "last_test": 2, "points": 10},
+            {"first_test": 3, "last_test": 4, "points": 20, "dependencies": [0]},
+            {"first_test": 5, "last_test": 6, "points": 30, "dependencies": [1]},
+            {"first_test": 7, "last_test": 8, "points": 40}
+        ]"#);
+        let tests = results(&[Verdict::Ok, Verdict::Ok, Verdict::Ok, Verdict::Wa, Verdict::Ok, Verdict::Ok, Verdict::Ok, Verdict::Ok]);
+        let (points, groups) = score(&config, &tests);
+        assert_eq!(points, 50);
+        assert_eq!(groups.iter().map(|g| (g.points, g.passed, g.skipped)).collect::<Vec<_>>(),
+            [(10, true, false), (0, false, false), (0, false, true), (40, true, false)]);
     }
 
-    let mut res_verdict = Verdict::Ok;
+    #[test]
+    fn each_test_points_are_floored() {
+        let config = config(r#"[{"first_test": 1, "last_test": 3, "points": 10, "policy": "each_test"}]"#);
+        let (points, groups) = score(&config, &results(&[Verdict::Ok, Verdict::Ok, Verdict::Wa]));
+        assert_eq!(points, 6);
+        assert!(!groups[0].passed);
 
-    for (test_number, handle) in tasks.into_iter().enumerate().map(|(n, h)| (n + 1, h)) {
-        let verdict = handle.await.unwrap();
-        tokio::fs::remove_file(format!("{DIR}/{}/{test_number}.out", solution.id)).await.unwrap();
-        if Verdict::Ok != verdict && res_verdict == Verdict::Ok {
-            res_verdict = verdict;
-        }
+        // the checker gave half of the test points
+        let mut tests = results(&[Verdict::Ok, Verdict::Pe, Verdict::Wa]);
+        tests[1].points = Some(0.5);
+        assert_eq!(score(&config, &tests).0, 5);
     }
 
-    tokio::fs::remove_dir_all(format!("{DIR}/{}", solution.id)).await.unwrap();
-    log::debug!("{res_verdict:?}");
-    Ok(res_verdict)
-}
\ No newline at end of file
+    #[test]
+    fn skipped_each_test_group_gets_nothing() {
+        let config = config(r#"[
+            {"first_test": 1, "last_test": 1, "points": 0},
+            {"first_test": 2, "last_test": 3, "points": 100, "dependencies": [0], "policy": "each_test"}
+        ]"#);
+        let (points, groups) = score(&config, &results(&[Verdict::Wa, Verdict::Ok, Verdict::Ok]));
+        assert_eq!(points, 0);
+        assert!(groups[1].skipped);
+    }
+}
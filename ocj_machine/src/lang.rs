@@ -0,0 +1,101 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use ocj_config::solution::{self, LangInfo};
+use serde::Deserialize;
+
+pub const REGISTRY: &str = "langs.json";
+
+fn one() -> f64 { 1.0 }
+fn yes() -> bool { true }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Lang {
+    pub name: Box<str>,
+    // file name of the source in the solution directory
+    pub source: Box<str>,
+    // {source}, {binary} and {dir} are replaced with absolute paths and {memory} with the memory limit in MB,
+    // both commands run sandboxed,
+    // the run command is started with an empty environment, so it needs an absolute program path
+    #[serde(default)]
+    pub compile: Option<Vec<Box<str>>>,
+    pub run: Vec<Box<str>>,
+
+    #[serde(default = "one")]
+    pub time_multiplier: f64,
+    #[serde(default = "one")]
+    pub memory_multiplier: f64,
+    // virtual machines start threads and reserve much more address space than they use,
+    // without the address space limit the run command has to pass {memory} to the runtime
+    #[serde(default)]
+    pub process_limit: Option<u64>,
+    #[serde(default = "yes")]
+    pub limit_address_space: bool,
+    // absolute paths of the runtime outside /usr, /lib and /etc, solutions see them read-only
+    #[serde(default)]
+    pub mounts: Vec<PathBuf>,
+}
+
+impl Lang {
+    pub fn expand(template: &[Box<str>], dir: &Path, source: &Path, binary: &Path, memory: u64) -> Vec<String> {
+        template.iter().map(|arg| arg
+            .replace("{source}", &source.to_string_lossy())
+            .replace("{binary}", &binary.to_string_lossy())
+            .replace("{dir}", &dir.to_string_lossy())
+            .replace("{memory}", &(memory >> 20).to_string())
+        ).collect()
+    }
+}
+
+pub struct Registry {
+    langs: BTreeMap<solution::Lang, Lang>,
+}
+
+impl Registry {
+    // without the registry file only C++ is available, as it was before languages were configurable
+    fn default_langs() -> BTreeMap<solution::Lang, Lang> {
+        BTreeMap::from([(solution::Lang("Cpp".into()), Lang {
+            name: "GNU C++".into(),
+            source: "solution.cpp".into(),
+            compile: Some(["g++", "-O2", "{source}", "-o", "{binary}"].map(Box::from).to_vec()),
+            run: vec!["{binary}".into()],
+            time_multiplier: 1.0,
+            memory_multiplier: 1.0,
+            process_limit: None,
+            limit_address_space: true,
+            mounts: Vec::new(),
+        })])
+    }
+
+    pub async fn load(path: &Path) -> Result<Self, ()> {
+        let langs = match tokio::fs::read_to_string(path).await {
+            Ok(data) => match serde_json::from_str(&data) {
+                Ok(langs) => langs,
+                Err(e) => {
+                    log::error!("can't parse language registry {}: {e}", path.display());
+                    return Err(());
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::warn!("language registry {} not found, only C++ is available", path.display());
+                Self::default_langs()
+            }
+            Err(e) => {
+                log::error!("can't read language registry {}: {e}", path.display());
+                return Err(());
+            }
+        };
+        if let Some((lang, _)) = langs.iter().find(|(_, l)| l.run.is_empty()) {
+            log::error!("language {} has an empty run command", lang.0);
+            return Err(());
+        }
+        Ok(Self { langs })
+    }
+
+    pub fn get(&self, lang: &solution::Lang) -> Option<&Lang> {
+        self.langs.get(lang)
+    }
+
+    pub fn infos(&self) -> Vec<LangInfo> {
+        self.langs.iter().map(|(lang, l)| LangInfo { lang: lang.clone(), name: l.name.clone() }).collect()
+    }
+}
@@ -1,27 +1,54 @@
 mod server;
 mod judge;
 mod file;
+mod sandbox;
+mod checker;
+mod package;
+mod lang;
+mod health;
 
-use ocj_config::{self as config, solution::{JudgeResult, Verdict}};
+use ocj_config::{self as config, auth::Token, machine, solution::{self, JudgeResult}};
 
-use std::{net::IpAddr, str::FromStr, sync::Arc};
-use tokio::{io::AsyncWriteExt, sync::{mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender}, Mutex}};
+use std::{collections::BTreeSet, net::IpAddr, path::Path, str::FromStr, sync::Arc};
+use tokio::{io::AsyncWriteExt, sync::{mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender}, Mutex, RwLock, Semaphore}};
 
 use server::{InputMsg, OutputMsg};
 
 #[derive(Clone)]
 struct App {
+    id: machine::Id,
+    token: Token,
+    langs: Arc<lang::Registry>,
+    // reported in heartbeats and on connection, a solution is in it from its arrival until its result is sent
+    judging: Arc<Mutex<BTreeSet<solution::Id>>>,
+    log_limit: usize,
+    // solutions judged at once, the server sends no more than that
+    slots: usize,
+    slots_semaphore: Arc<Semaphore>,
+    // tests run at once inside a solution
+    test_concurrency: usize,
+    // solutions hold it for reading, so tests are not replaced under them
+    tests: Arc<RwLock<()>>,
     statements_exist: Arc<Mutex<bool>>,
     ws_receiver: Arc<Mutex<UnboundedReceiver<InputMsg>>>,
     ws_sender: UnboundedSender<OutputMsg>,
 }
 
 impl App {
-    pub fn init() -> (App, UnboundedSender<InputMsg>, UnboundedReceiver<OutputMsg>) {
+    pub fn init(id: machine::Id, token: Token, langs: lang::Registry, log_limit: usize, slots: usize, test_concurrency: usize) -> (App, UnboundedSender<InputMsg>, UnboundedReceiver<OutputMsg>) {
         let (i_sender, i_receiver) = unbounded_channel();
         let (o_sender, o_receiver) = unbounded_channel();
         (
             Self {
+                id,
+                token,
+                langs: Arc::new(langs),
+                judging: Arc::new(Mutex::new(BTreeSet::new())),
+                log_limit,
+                slots,
+                slots_semaphore: Arc::new(Semaphore::new(slots)),
+                test_concurrency,
+                tests: Arc::new(RwLock::new(())),
                 statements_exist: Arc::new(Mutex::new(true)),
                 ws_sender: o_sender,
                 ws_receiver: Arc::new(Mutex::new(i_receiver)),
@@ -32,7 +59,6 @@ impl App {
     }
 
     pub async fn run(&mut self) {
-        self.ws_sender.send(OutputMsg::Init).unwrap();
         loop {
             let msg = self.ws_receiver.lock().await.recv().await;
             let msg = if let Some(msg) = msg {
@@ -43,38 +69,78 @@ impl App {
             };
             match msg {
                 InputMsg::UpdateTests(bytes) => {
+                    let _tests = self.tests.write().await;
                     log::info!("tests was updated");
                     let mut tests = tokio::fs::File::create(format!("{}.tar.gz", config::file::TESTS)).await.unwrap();
                     tests.write(&bytes).await.unwrap();
                     file::decompress_tests().await;
+                    let errors = package::prepare().await;
+                    self.ws_sender.send(OutputMsg::TestsUpdated(errors)).unwrap();
                 },
                 InputMsg::JudgeSolution(solution) => {
-                    log::info!("judge solution [{}]", solution.id);
-                    let solution_id = solution.id;
-                    let problem_number = solution.problem_number;
-                    let verdict = crate::judge::judge(solution).await;
-                    let verdict = if let Ok(v) = verdict {
-                        v
-                    } else {
+                    // the server sends a solution again when it thinks it was lost,
+                    // both judgings would share the solution directory, the running one answers for both
+                    if !self.judging.lock().await.insert(solution.id) {
+                        log::warn!("solution [{}] is already being judged", solution.id);
                         continue;
-                    };
-                    self.ws_sender.send(OutputMsg::JudgeResult(JudgeResult {
-                        score: if let Verdict::Ok = verdict {100} else {0},
-                        solution_id,
-                        verdict,
-                        problem_number,
-                    })).unwrap();
+                    }
+                    let app = self.clone();
+                    tokio::spawn(async move { app.judge(solution).await });
                 }
-                ocj_config::msg::ServerToMachine::InitFailed => todo!(),
+                // the server has no tests yet, they come with UpdateTests when an admin uploads them
+                InputMsg::InitFailed => log::warn!("server has no tests yet, waiting for them"),
+                // the connection stops on them before they get here
+                ocj_config::msg::ServerToMachine::Unauthorized | ocj_config::msg::ServerToMachine::IdTaken => {},
             };
         };
     }
+
+    async fn judge(&self, solution: solution::Solution) {
+        let _slot = self.slots_semaphore.acquire().await.unwrap();
+        let _tests = self.tests.read().await;
+        log::info!("judge solution [{}]", solution.id);
+        let solution_id = solution.id;
+        let problem_number = solution.problem_number;
+        let ws_sender = self.ws_sender.clone();
+        let progress = move |progress| {
+            _ = ws_sender.send(OutputMsg::Progress { solution_id, progress });
+        };
+        let report = crate::judge::judge(solution, &self.langs, self.log_limit, self.test_concurrency, progress).await;
+        self.ws_sender.send(OutputMsg::JudgeResult(JudgeResult {
+            score: report.score,
+            solution_id,
+            verdict: report.verdict,
+            problem_number,
+            time: report.time.as_millis() as u64,
+            memory: report.memory / 1024,
+            tests: report.tests,
+            failed_test: report.failed_test,
+            groups: report.groups,
+            compile_output: report.compile_output,
+        })).unwrap();
+    }
+}
+
+fn parse_arg<T: FromStr>(arg: Option<String>, number: usize, name: &str, default: T) -> Result<T, ()> {
+    match arg.map(|a| a.parse()) {
+        Some(Ok(value)) => Ok(value),
+        Some(Err(_)) => {
+            log::error!("can't parse arg {number} to {name}");
+            Err(())
+        }
+        None => Ok(default),
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), ()>{
     env_logger::init();
     file::init().await;
+    // without namespaces and separate uids a solution could read the tests and the token
+    if !sandbox::is_root() {
+        log::error!("machine must run as root to sandbox solutions");
+        return Err(());
+    }
 
     let mut args = std::env::args();
     let ip = args.nth(1);
@@ -90,7 +156,20 @@ async fn main() -> Result<(), ()>{
         }
     };
 
-    let (app, i_sender, o_receiver) = App::init();
+    let langs = lang::Registry::load(Path::new(&args.next().unwrap_or(lang::REGISTRY.to_string()))).await?;
+
+    let log_limit = parse_arg(args.next(), 3, "compiler output size limit", judge::LOG_LIMIT)?;
+    let slots = parse_arg(args.next(), 4, "judging slots count", 1)?;
+    let test_concurrency = parse_arg(args.next(), 5, "tests run at once", 1)?;
+    if slots == 0 || test_concurrency == 0 {
+        log::error!("judging slots count and tests run at once must be positive");
+        return Err(());
+    }
+
+    let id = file::load_id().await?;
+    let token = file::load_token().await?;
+
+    let (app, i_sender, o_receiver) = App::init(id, token, langs, log_limit, slots, test_concurrency);
     let mut app_clone = app.clone();
     let _app_task = tokio::spawn(async move {app_clone.run().await});
 
@@ -0,0 +1,113 @@
+use std::{path::Path, process::Stdio, time::Duration};
+
+use ocj_config::{self as config, solution::ProblemNum, tests::{Config, PackageError, CHECKER, INTERACTOR, VALIDATOR}};
+use tokio::{fs, process::Command};
+
+use crate::{checker, judge, sandbox};
+
+const VALIDATOR_LIMITS: sandbox::Limits = sandbox::Limits {
+    cpu_time: Duration::from_secs(10),
+    wall_time: Duration::from_secs(20),
+    memory: 1 << 30,
+    output: 1 << 20,
+    processes: 1,
+    address_space: true,
+};
+
+// compiled binaries stay next to the sources until the next tests update
+async fn compile(dir: &Path, name: &str) -> Result<bool, Box<str>> {
+    let source = dir.join(format!("{name}.cpp"));
+    if !fs::try_exists(&source).await.unwrap_or(false) {
+        return Ok(false);
+    }
+    let output = Command::new("g++")
+        .args(["-O2", "-std=c++17", "-I"]).arg(dir)
+        .arg("-o").arg(dir.join(name))
+        .arg(&source)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(judge::COMPILE_TIME_LIMIT, output).await
+        .map_err(|_| format!("{name} compilation took more than {} s", judge::COMPILE_TIME_LIMIT.as_secs()))?
+        .map_err(|e| format!("can't run compiler for {name}: {e}"))?;
+    if output.status.success() {
+        Ok(true)
+    } else {
+        Err(format!("{name} compilation failed: {}", checker::comment(&output.stderr)).into())
+    }
+}
+
+// the validator is sandboxed like a solution and sees only itself
+async fn validate(dir: &Path, config: &Config) -> Result<(), Box<str>> {
+    let validator = dir.join(VALIDATOR);
+    let log_path = dir.join(format!("{VALIDATOR}.log"));
+    for test_number in 1..=config.test_count {
+        let input = std::fs::File::open(dir.join(format!("{test_number}.in"))).map_err(|e| format!("test {test_number} input: {e}"))?;
+        let log = std::fs::File::create(&log_path).map_err(|e| format!("can't create validator log: {e}"))?;
+        let outcome = sandbox::run(sandbox::Run {
+            program: &validator,
+            args: Vec::new(),
+            stdin: input.into(),
+            stdout: Stdio::null(),
+            stderr: log.into(),
+            limits: &VALIDATOR_LIMITS,
+            mounts: vec![sandbox::Mount::read_only(&validator)],
+            env: &[],
+        }, Path::new("/tmp")).await.map_err(|e| format!("can't run validator: {e}"))?;
+        if outcome.time_exceeded(&VALIDATOR_LIMITS) || outcome.idleness_exceeded() {
+            return Err(format!("validator took more than {} s on test {test_number}", VALIDATOR_LIMITS.cpu_time.as_secs()).into());
+        }
+        if !outcome.status.success() {
+            let log = fs::read(&log_path).await.unwrap_or_default();
+            return Err(format!("test {test_number} is not valid: {}", checker::comment(&log)).into());
+        }
+    }
+    _ = fs::remove_file(&log_path).await;
+    Ok(())
+}
+
+async fn prepare_problem(dir: &Path) -> Result<(), Box<str>> {
+    let config = fs::read_to_string(dir.join(format!("{}.json", config::file::PROBLEM_TEST_CONFIG))).await
+        .map_err(|e| format!("can't read config: {e}"))?;
+    let config: Config = serde_json::from_str(&config).map_err(|e| format!("can't parse config: {e}"))?;
+    config.check_groups()?;
+
+    compile(dir, CHECKER).await?;
+    compile(dir, INTERACTOR).await?;
+    if compile(dir, VALIDATOR).await? {
+        validate(dir, &config).await?;
+    }
+    Ok(())
+}
+
+pub async fn prepare() -> Vec<PackageError> {
+    let mut errors = Vec::new();
+    let mut entries = match fs::read_dir(config::file::TESTS).await {
+        Ok(e) => e,
+        Err(e) => {
+            log::error!("can't read tests directory: {e}");
+            return errors;
+        }
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let problem_number: ProblemNum = if let Some(n) = entry.file_name().to_str().and_then(|n| n.parse().ok()) {n} else {
+            continue;
+        };
+        // sandboxed runs need absolute paths
+        let dir = match fs::canonicalize(entry.path()).await {
+            Ok(dir) => dir,
+            Err(e) => {
+                log::error!("can't find problem {problem_number} directory: {e}");
+                continue;
+            }
+        };
+        match prepare_problem(&dir).await {
+            Ok(()) => log::info!("problem {problem_number} is prepared"),
+            Err(error) => {
+                log::error!("problem {problem_number} package error: {error}");
+                errors.push(PackageError { problem_number, error });
+            }
+        }
+    }
+    errors
+}
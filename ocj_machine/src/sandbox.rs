@@ -0,0 +1,479 @@
+use std::{ffi::{CString, OsStr}, io, os::unix::{ffi::OsStrExt, process::{CommandExt, ExitStatusExt}}, path::Path, process::{Command, ExitStatus, Stdio}, sync::{LazyLock, Mutex}, time::Duration};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::{config::tests, lang::Lang};
+
+// every run gets its own uid, so RLIMIT_NPROC counts only processes of this run
+pub const UID_BASE: u32 = 60000;
+pub const USERS_COUNT: u32 = 64;
+
+// an empty directory the root of every run is mounted on
+pub const JAIL: &str = "jail";
+// the language runtime, everything else of the machine is hidden from a run
+const RUNTIME: &[&str] = &["/bin", "/sbin", "/lib", "/lib32", "/lib64", "/libx32", "/usr", "/etc"];
+const DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/random", "/dev/urandom"];
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xC000_003E;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+// the C library of python and java tries the nscd socket on startup, so these fail instead of killing
+const FAILING_SYSCALLS: &[libc::c_long] = &[libc::SYS_socket, libc::SYS_connect];
+const DENIED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_bind, libc::SYS_listen, libc::SYS_accept, libc::SYS_accept4,
+    libc::SYS_ptrace, libc::SYS_process_vm_readv, libc::SYS_process_vm_writev,
+    libc::SYS_mount, libc::SYS_umount2, libc::SYS_pivot_root, libc::SYS_chroot,
+    libc::SYS_unshare, libc::SYS_setns, libc::SYS_setsid, libc::SYS_setpgid,
+    libc::SYS_sethostname, libc::SYS_setdomainname,
+    libc::SYS_reboot, libc::SYS_kexec_load, libc::SYS_init_module, libc::SYS_finit_module, libc::SYS_delete_module,
+    libc::SYS_swapon, libc::SYS_swapoff, libc::SYS_bpf, libc::SYS_perf_event_open,
+    libc::SYS_keyctl, libc::SYS_add_key, libc::SYS_request_key,
+    // io_uring does its work, sockets included, without system calls seccomp could see
+    libc::SYS_io_uring_setup, libc::SYS_io_uring_enter, libc::SYS_io_uring_register,
+];
+// x32 system calls are the x86_64 ones with this bit set, the denylist would not match them
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub cpu_time: Duration,
+    pub wall_time: Duration,
+    pub memory: u64,
+    pub output: u64,
+    pub processes: u64,
+    pub address_space: bool,
+}
+
+impl From<&tests::Config> for Limits {
+    fn from(config: &tests::Config) -> Self {
+        Self {
+            cpu_time: Duration::from_millis(config.time_limit),
+            wall_time: Duration::from_millis(config.wall_time_limit.unwrap_or(config.time_limit * 3)),
+            memory: config.memory_limit * 1024 * 1024,
+            output: config.output_limit * 1024 * 1024,
+            processes: config.process_limit,
+            address_space: true,
+        }
+    }
+}
+
+impl Limits {
+    pub fn for_lang(&self, lang: &Lang) -> Self {
+        Self {
+            cpu_time: self.cpu_time.mul_f64(lang.time_multiplier),
+            wall_time: self.wall_time.mul_f64(lang.time_multiplier),
+            memory: (self.memory as f64 * lang.memory_multiplier) as u64,
+            output: self.output,
+            processes: lang.process_limit.unwrap_or(self.processes),
+            address_space: lang.limit_address_space,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Outcome {
+    pub status: ExitStatus,
+    pub wall_time_exceeded: bool,
+    pub cpu_time: Duration,
+    // bytes
+    pub peak_memory: u64,
+}
+
+impl Outcome {
+    pub fn time_exceeded(&self, limits: &Limits) -> bool {
+        self.cpu_time > limits.cpu_time || self.status.signal() == Some(libc::SIGXCPU)
+    }
+
+    // the cpu time is within the limit, so the solution was waiting
+    pub fn idleness_exceeded(&self) -> bool {
+        self.wall_time_exceeded
+    }
+
+    pub fn memory_exceeded(&self, limits: &Limits) -> bool {
+        self.peak_memory > limits.memory
+    }
+
+    pub fn output_exceeded(&self) -> bool {
+        self.status.signal() == Some(libc::SIGXFSZ)
+    }
+
+    // seccomp kills the process on a denied system call
+    pub fn security_violated(&self) -> bool {
+        self.status.signal() == Some(libc::SIGSYS)
+    }
+}
+
+struct Users {
+    free: Mutex<Vec<u32>>,
+    semaphore: Semaphore,
+}
+
+static USERS: LazyLock<Users> = LazyLock::new(|| Users {
+    free: Mutex::new((UID_BASE..UID_BASE + USERS_COUNT).collect()),
+    semaphore: Semaphore::new(USERS_COUNT as usize),
+});
+
+struct User {
+    uids: Vec<u32>,
+    _permit: SemaphorePermit<'static>,
+}
+
+impl User {
+    // all uids at once, so runs that need several can't deadlock each other
+    async fn acquire(count: u32) -> Self {
+        let permit = USERS.semaphore.acquire_many(count).await.unwrap();
+        let mut free = USERS.free.lock().unwrap();
+        let at = free.len() - count as usize;
+        Self { uids: free.split_off(at), _permit: permit }
+    }
+
+    fn uid(&self, i: usize) -> u32 {
+        self.uids[i]
+    }
+}
+
+impl Drop for User {
+    fn drop(&mut self) {
+        USERS.free.lock().unwrap().append(&mut self.uids);
+    }
+}
+
+fn seccomp_filter() -> Vec<libc::sock_filter> {
+    let stmt = |code: u32, k: u32| libc::sock_filter { code: code as u16, jt: 0, jf: 0, k };
+    let jeq = |k: u32, jt: u8, jf: u8| libc::sock_filter { code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16, jt, jf, k };
+
+    let mut filter = vec![
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 4),
+        jeq(AUDIT_ARCH, 1, 0),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 0),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    {
+        filter.push(libc::sock_filter { code: (libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K) as u16, jt: 0, jf: 1, k: X32_SYSCALL_BIT });
+        filter.push(stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS));
+    }
+    for nr in FAILING_SYSCALLS {
+        filter.push(jeq(*nr as u32, 0, 1));
+        filter.push(stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ERRNO | libc::EPERM as u32));
+    }
+    for nr in DENIED_SYSCALLS {
+        filter.push(jeq(*nr as u32, 0, 1));
+        filter.push(stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS));
+    }
+    filter.push(stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+    filter
+}
+
+fn check(r: libc::c_int) -> io::Result<()> {
+    if r < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn set_rlimit(resource: libc::__rlimit_resource_t, soft: u64, hard: u64) -> io::Result<()> {
+    let limit = libc::rlimit { rlim_cur: soft, rlim_max: hard };
+    check(unsafe { libc::setrlimit(resource, &limit) })
+}
+
+fn cstring(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(io::Error::other)
+}
+
+// the flags a read-only remount has to keep, the kernel refuses to clear them
+fn mount_flags(path: &Path) -> io::Result<libc::c_ulong> {
+    let path = cstring(path)?;
+    let mut stat = unsafe { std::mem::zeroed::<libc::statvfs>() };
+    check(unsafe { libc::statvfs(path.as_ptr(), &mut stat) })?;
+    Ok([
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ].iter().filter(|(st, _)| stat.f_flag & st != 0).fold(0, |flags, (_, ms)| flags | ms))
+}
+
+// paths a run sees besides the runtime, at the same place as on the machine
+pub struct Mount<'a> {
+    pub path: &'a Path,
+    pub writable: bool,
+}
+
+impl<'a> Mount<'a> {
+    pub fn read_only(path: &'a Path) -> Self {
+        Self { path, writable: false }
+    }
+
+    pub fn writable(path: &'a Path) -> Self {
+        Self { path, writable: true }
+    }
+}
+
+struct Bind {
+    // directories from the jail root down to the target
+    parents: Vec<CString>,
+    source: CString,
+    target: CString,
+    dir: bool,
+    remount: libc::c_ulong,
+}
+
+struct Symlink {
+    target: CString,
+    link: CString,
+}
+
+// everything the child needs to build the root of a run, prepared before the fork
+struct Jail {
+    root: CString,
+    binds: Vec<Bind>,
+    symlinks: Vec<Symlink>,
+    tmp: CString,
+    tmp_options: CString,
+    proc: CString,
+    cwd: CString,
+}
+
+impl Jail {
+    fn new(mounts: &[Mount], limits: &Limits, dir: &Path) -> io::Result<Self> {
+        let root = std::fs::canonicalize(JAIL)?;
+        let mut binds = Vec::new();
+        let mut symlinks = Vec::new();
+        let mut bind = |path: &Path, writable: bool| -> io::Result<()> {
+            let inside = root.join(path.strip_prefix("/").map_err(io::Error::other)?);
+            let parents = inside.ancestors().skip(1).take_while(|p| *p != root.as_path()).collect::<Vec<_>>().into_iter().rev()
+                .map(cstring).collect::<io::Result<_>>()?;
+            let readonly = if writable {0} else {libc::MS_RDONLY};
+            binds.push(Bind {
+                parents,
+                source: cstring(path)?,
+                target: cstring(&inside)?,
+                dir: path.is_dir(),
+                remount: libc::MS_BIND | libc::MS_REMOUNT | libc::MS_NOSUID | readonly | mount_flags(path)?,
+            });
+            Ok(())
+        };
+        for path in RUNTIME.iter().map(Path::new) {
+            match std::fs::symlink_metadata(path) {
+                Ok(m) if m.is_symlink() => symlinks.push(Symlink {
+                    target: cstring(&std::fs::read_link(path)?)?,
+                    link: cstring(&root.join(path.strip_prefix("/").unwrap()))?,
+                }),
+                Ok(_) => bind(path, false)?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+                Err(e) => return Err(e),
+            }
+        }
+        // devices are not remounted read-only, the solution writes to /dev/null
+        for path in DEVICES.iter().map(Path::new) {
+            bind(path, true)?;
+        }
+        let mut mounts: Vec<&Mount> = mounts.iter().collect();
+        // parents are mounted before what is inside them
+        mounts.sort_by_key(|m| m.path);
+        for mount in mounts {
+            if !mount.path.is_absolute() {
+                return Err(io::Error::other(format!("mount {} is not an absolute path", mount.path.display())));
+            }
+            bind(mount.path, mount.writable)?;
+        }
+        Ok(Self {
+            tmp: cstring(&root.join("tmp"))?,
+            proc: cstring(&root.join("proc"))?,
+            tmp_options: CString::new(format!("size={},mode=1777", limits.output)).map_err(io::Error::other)?,
+            cwd: cstring(&std::fs::canonicalize(dir)?)?,
+            root: cstring(&root)?,
+            binds,
+            symlinks,
+        })
+    }
+
+    // runs in the forked child, so it must not allocate
+    fn enter(&self) -> io::Result<()> {
+        let none = std::ptr::null::<libc::c_char>();
+        let mount = |source: *const libc::c_char, target: *const libc::c_char, fstype: *const libc::c_char, flags: libc::c_ulong, data: *const libc::c_char| {
+            check(unsafe { libc::mount(source, target, fstype, flags, data as *const libc::c_void) })
+        };
+        let mkdir = |path: &CString| {
+            if unsafe { libc::mkdir(path.as_ptr(), 0o755) } < 0 && io::Error::last_os_error().raw_os_error() != Some(libc::EEXIST) {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        };
+        let tmpfs = c"tmpfs".as_ptr();
+
+        // nothing mounted here leaks to the machine
+        mount(none, c"/".as_ptr(), none, libc::MS_REC | libc::MS_PRIVATE, none)?;
+        mount(tmpfs, self.root.as_ptr(), tmpfs, libc::MS_NOSUID | libc::MS_NODEV, c"size=1m,mode=755".as_ptr())?;
+        // before the binds, some of them may be inside
+        mkdir(&self.tmp)?;
+        mount(tmpfs, self.tmp.as_ptr(), tmpfs, libc::MS_NOSUID | libc::MS_NODEV, self.tmp_options.as_ptr())?;
+        for bind in &self.binds {
+            for parent in &bind.parents {
+                mkdir(parent)?;
+            }
+            if bind.dir {
+                mkdir(&bind.target)?;
+            } else {
+                let fd = unsafe { libc::open(bind.target.as_ptr(), libc::O_CREAT | libc::O_WRONLY | libc::O_CLOEXEC, 0o644) };
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                unsafe { libc::close(fd) };
+            }
+            mount(bind.source.as_ptr(), bind.target.as_ptr(), none, libc::MS_BIND | libc::MS_REC, none)?;
+            mount(none, bind.target.as_ptr(), none, bind.remount, none)?;
+        }
+        for symlink in &self.symlinks {
+            check(unsafe { libc::symlink(symlink.target.as_ptr(), symlink.link.as_ptr()) })?;
+        }
+        // the loader finds libraries of virtual machines through /proc/self/exe, processes of other uids are hidden
+        mkdir(&self.proc)?;
+        mount(c"proc".as_ptr(), self.proc.as_ptr(), c"proc".as_ptr(), libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC, c"hidepid=2".as_ptr())?;
+
+        // the old root is stacked under the new one and detached
+        check(unsafe { libc::chdir(self.root.as_ptr()) })?;
+        check(unsafe { libc::syscall(libc::SYS_pivot_root, c".".as_ptr(), c".".as_ptr()) } as libc::c_int)?;
+        check(unsafe { libc::umount2(c".".as_ptr(), libc::MNT_DETACH) })?;
+        check(unsafe { libc::chdir(c"/".as_ptr()) })?;
+        mount(none, c"/".as_ptr(), none, libc::MS_REMOUNT | libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV, none)?;
+        check(unsafe { libc::chdir(self.cwd.as_ptr()) })
+    }
+}
+
+// runs in the forked child right before exec, so it must not allocate
+fn confine(limits: &Limits, jail: &Jail, uid: u32, filter: &[libc::sock_filter]) -> io::Result<()> {
+    check(unsafe { libc::setsid() })?;
+
+    let cpu = limits.cpu_time.as_millis().div_ceil(1000) as u64;
+    set_rlimit(libc::RLIMIT_CPU, cpu, cpu + 1)?;
+    // address space is not what the limit is about, peak rss is checked after the run
+    if limits.address_space {
+        set_rlimit(libc::RLIMIT_AS, limits.memory * 2, limits.memory * 2)?;
+    }
+    set_rlimit(libc::RLIMIT_STACK, limits.memory, limits.memory)?;
+    set_rlimit(libc::RLIMIT_FSIZE, limits.output, limits.output)?;
+    set_rlimit(libc::RLIMIT_NPROC, limits.processes, limits.processes)?;
+    set_rlimit(libc::RLIMIT_CORE, 0, 0)?;
+
+    check(unsafe { libc::unshare(libc::CLONE_NEWNS | libc::CLONE_NEWNET | libc::CLONE_NEWIPC | libc::CLONE_NEWUTS) })?;
+    jail.enter()?;
+    check(unsafe { libc::setgroups(0, std::ptr::null()) })?;
+    check(unsafe { libc::setgid(uid) })?;
+    check(unsafe { libc::setuid(uid) })?;
+
+    check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+    let program = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_ptr() as *mut libc::sock_filter,
+    };
+    check(unsafe { libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &program as *const libc::sock_fprog) })
+}
+
+pub fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+pub struct Run<'a> {
+    pub program: &'a Path,
+    pub args: Vec<&'a OsStr>,
+    pub stdin: Stdio,
+    pub stdout: Stdio,
+    pub stderr: Stdio,
+    pub limits: &'a Limits,
+    pub mounts: Vec<Mount<'a>>,
+    // the environment is empty otherwise
+    pub env: &'a [(&'a str, &'a str)],
+}
+
+// the command with its pipe ends is dropped here, so the parent keeps none of them open
+fn spawn(run: Run, dir: &Path, uid: u32) -> io::Result<libc::pid_t> {
+    let filter = seccomp_filter();
+    let jail = Jail::new(&run.mounts, run.limits, dir)?;
+    let mut command = Command::new(run.program);
+    command.args(run.args)
+        .current_dir(dir)
+        .env_clear()
+        .envs(run.env.iter().copied())
+        .stdin(run.stdin)
+        .stdout(run.stdout)
+        .stderr(run.stderr);
+    let confine_limits = run.limits.clone();
+    unsafe {
+        command.pre_exec(move || confine(&confine_limits, &jail, uid, &filter));
+    }
+    Ok(command.spawn()?.id() as libc::pid_t)
+}
+
+pub async fn run(run: Run<'_>, dir: &Path) -> io::Result<Outcome> {
+    let user = User::acquire(1).await;
+    let limits = run.limits;
+    let pid = spawn(run, dir, user.uid(0))?;
+    wait_outcome(pid, limits).await
+}
+
+// the solution and the interactor talk through pipes instead of stdin and stdout
+pub async fn run_interactive(solution: Run<'_>, interactor: Run<'_>, dir: &Path) -> io::Result<(Outcome, Outcome)> {
+    let user = User::acquire(2).await;
+    let (solution_limits, interactor_limits) = (solution.limits, interactor.limits);
+    let solution_pid = spawn(solution, dir, user.uid(0))?;
+    let interactor_pid = match spawn(interactor, dir, user.uid(1)) {
+        Ok(pid) => pid,
+        Err(e) => {
+            unsafe { libc::killpg(solution_pid, libc::SIGKILL) };
+            _ = wait_outcome(solution_pid, solution_limits).await;
+            return Err(e);
+        }
+    };
+    let (solution, interactor) = tokio::join!(wait_outcome(solution_pid, solution_limits), wait_outcome(interactor_pid, interactor_limits));
+    Ok((solution?, interactor?))
+}
+
+pub fn pipe() -> io::Result<(Stdio, Stdio)> {
+    let (reader, writer) = io::pipe()?;
+    Ok((reader.into(), writer.into()))
+}
+
+async fn wait_outcome(pid: libc::pid_t, limits: &Limits) -> io::Result<Outcome> {
+    // the child is reaped by wait4 below to get its rusage
+    let mut waiter = tokio::task::spawn_blocking(move || wait(pid));
+
+    let (result, wall_time_exceeded) = match tokio::time::timeout(limits.wall_time, &mut waiter).await {
+        Ok(r) => (r, false),
+        Err(_) => {
+            unsafe { libc::killpg(pid, libc::SIGKILL) };
+            (waiter.await, true)
+        }
+    };
+    unsafe { libc::killpg(pid, libc::SIGKILL) };
+
+    let (status, usage) = result.map_err(io::Error::other)??;
+    let time = |t: libc::timeval| Duration::new(t.tv_sec as u64, t.tv_usec as u32 * 1000);
+    Ok(Outcome {
+        status,
+        wall_time_exceeded,
+        cpu_time: time(usage.ru_utime) + time(usage.ru_stime),
+        peak_memory: usage.ru_maxrss as u64 * 1024,
+    })
+}
+
+fn wait(pid: libc::pid_t) -> io::Result<(ExitStatus, libc::rusage)> {
+    let mut status = 0;
+    let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    loop {
+        if unsafe { libc::wait4(pid, &mut status, 0, &mut usage) } >= 0 {
+            return Ok((ExitStatus::from_raw(status), usage));
+        }
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+}
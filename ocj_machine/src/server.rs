@@ -1,57 +1,110 @@
-use crate::config;
-use std::net::IpAddr;
+use crate::config::{self, machine::HEARTBEAT_PERIOD};
+use std::{net::IpAddr, time::Duration};
 
 use futures::{stream::StreamExt, SinkExt};
 
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use tokio::{net::TcpStream, sync::mpsc::{UnboundedReceiver, UnboundedSender}};
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream};
 
-use crate::App;
+use crate::{file, health, App};
 
 pub type InputMsg = config::msg::ServerToMachine;
 pub type OutputMsg = config::msg::MachineToServer;
 
-pub async fn run(ip: &IpAddr, _app: App, i_sender: UnboundedSender<InputMsg>, mut o_receiver: UnboundedReceiver<OutputMsg>) -> Result<(), ()> {
-    let ws_addr = format!("ws://{}:{}", ip.to_string(), config::port::WS_FOR_MACHINE);
-    let ws = if let Ok((ws, _)) = connect_async(ws_addr).await {
-        ws
-    } else {
-        log::error!("can't connect to server with websockets");
-        return Err(())
-    };
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
 
+// reconnects until the app is closed, messages to the server wait for the next connection
+pub async fn run(ip: &IpAddr, app: App, i_sender: UnboundedSender<InputMsg>, mut o_receiver: UnboundedReceiver<OutputMsg>) -> Result<(), ()> {
+    let ws_addr = format!("ws://{}:{}", ip, config::port::WS_FOR_MACHINE);
+    let mut backoff = MIN_BACKOFF;
+    let mut pending = None;
+    loop {
+        match connect_async(&ws_addr).await {
+            Ok((ws, _)) => {
+                log::info!("connected to server as machine [{}]", app.id);
+                backoff = MIN_BACKOFF;
+                session(ws, &app, &i_sender, &mut o_receiver, &mut pending).await?;
+                log::warn!("connection to server is lost");
+            }
+            Err(e) => log::error!("can't connect to server with websockets: {e}"),
+        }
+        log::info!("reconnecting in {} s", backoff.as_secs());
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+async fn send(write: &mut (impl SinkExt<Message> + Unpin), msg: &OutputMsg) -> bool {
+    write.send(Message::Text(serde_json::to_string(msg).unwrap().into())).await.is_ok()
+}
+
+// Ok when the connection is lost, Err when the app is closed or the machine is not authorized
+async fn session(
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    app: &App,
+    i_sender: &UnboundedSender<InputMsg>,
+    o_receiver: &mut UnboundedReceiver<OutputMsg>,
+    pending: &mut Option<OutputMsg>,
+) -> Result<(), ()> {
     let (mut write, mut read) = ws.split();
+    let judging = app.judging.lock().await.iter().copied().collect();
+    if !send(&mut write, &OutputMsg::Init { id: app.id, token: app.token, slots: app.slots as u16, langs: app.langs.infos(), judging }).await {
+        return Ok(());
+    }
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_PERIOD);
 
-    let i_task = tokio::spawn( async move {
-        while let Some(msg) = read.next().await {
-            let msg = if let Ok(Message::Text(data)) = msg {
-                data.to_string()
-            } else {
-                log::error!("incorrect input websocket message");
-                return Err(());
-            };
-            let msg = if let Ok(msg) = serde_json::from_str::<InputMsg>(&msg) {
-                msg
-            } else {
-                log::error!("incorrect input websocket message");
-                return Err(());
-            };
-
-            i_sender.send(msg).unwrap();
+    loop {
+        // a message is dropped only after it is sent
+        if let Some(msg) = pending.take() {
+            if !send(&mut write, &msg).await {
+                *pending = Some(msg);
+                return Ok(());
+            }
+            // a result lost with the connection is still reported as judging, so the server waits for it
+            if let OutputMsg::JudgeResult(result) = &msg {
+                app.judging.lock().await.remove(&result.solution_id);
+            }
         }
-        Ok(())
-    });
 
-    let o_task = tokio::spawn( async move {
-        while let Some(msg) = o_receiver.recv().await {
-            write.send(Message::Text(serde_json::to_string(&msg).unwrap().into())).await.unwrap();
+        tokio::select! {
+            msg = read.next() => {
+                let data = match msg {
+                    Some(Ok(Message::Text(data))) => data,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return Ok(()),
+                    Some(Ok(_)) => continue,
+                };
+                let msg = match serde_json::from_str::<InputMsg>(&data) {
+                    Ok(InputMsg::Unauthorized) => {
+                        log::error!("server rejected the machine token");
+                        return Err(());
+                    }
+                    Ok(InputMsg::IdTaken) => {
+                        log::error!("machine id [{}] belongs to another machine, a new one is made on the next start", app.id);
+                        if let Err(e) = tokio::fs::remove_file(file::ID).await {
+                            log::error!("can't remove machine id file {}: {e}", file::ID);
+                        }
+                        return Err(());
+                    }
+                    Ok(msg) => msg,
+                    Err(_) => {
+                        log::error!("incorrect input websocket message");
+                        return Ok(());
+                    }
+                };
+                if i_sender.send(msg).is_err() {
+                    return Err(());
+                }
+            }
+            msg = o_receiver.recv() => {
+                *pending = Some(msg.ok_or(())?);
+            }
+            _ = heartbeat.tick() => {
+                let judging = app.judging.lock().await.iter().copied().collect();
+                if !send(&mut write, &OutputMsg::Heartbeat(health::health(judging).await)).await {
+                    return Ok(());
+                }
+            }
         }
-    });
-
-    tokio::select! {
-        _ = o_task => {},
-        _ = i_task => {},
     }
-
-    Ok(())
-}
\ No newline at end of file
+}
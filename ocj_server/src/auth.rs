@@ -1,10 +1,11 @@
 use std::{collections::HashMap, net::IpAddr};
+use argon2::{password_hash::{rand_core::OsRng, SaltString}, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use sha2::{Digest, Sha256};
 use tokio::sync::Mutex;
 
 use rand::TryRngCore;
 
-use crate::{error::{self, OcjError}, Result, config};
+use crate::{client, error::{self, OcjError}, Result, config};
 
 use config::auth::Token;
 
@@ -21,6 +22,27 @@ pub fn hash(s: &str) -> u128 {
     u128::from_le_bytes(hash_key)
 }
 
+// tokens are random, so a fast hash is enough to keep them out of the storage log
+pub fn hash_token(token: &Token) -> u128 {
+    hash(&token.to_string())
+}
+
+// passwords are chosen by people and can be guessed, unlike tokens they are hashed slowly and with a salt
+pub async fn hash_password(password: &str) -> Result<Box<str>> {
+    let password = password.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default().hash_password(password.as_bytes(), &salt).map(|h| h.to_string().into())
+    }).await?.map_err(OcjError::PasswordHash)
+}
+
+pub async fn verify_password(password: &str, hash: &str) -> bool {
+    let (password, hash) = (password.to_owned(), hash.to_owned());
+    tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&hash).is_ok_and(|h| Argon2::default().verify_password(password.as_bytes(), &h).is_ok())
+    }).await.unwrap_or(false)
+}
+
 pub fn gen_token() -> Result<Token> {
     let mut rng = rand::rngs::OsRng;
     let a= rng.try_next_u64().or_else(|e| Err(error::OcjError::RngCore(e)))? as u128;
@@ -31,6 +53,9 @@ pub fn gen_token() -> Result<Token> {
 pub struct Service {
     hash_key: u128, 
     tokens: Mutex<HashMap<IpAddr, Token>>, 
+    // participants and machine names by the hashes of their tokens
+    client_tokens: Mutex<HashMap<u128, client::Id>>,
+    machine_tokens: Mutex<HashMap<u128, Box<str>>>,
 }
 
 impl Service {
@@ -38,6 +63,8 @@ impl Service {
         Self {
             hash_key: hash(key),
             tokens: Mutex::new(HashMap::new()),
+            client_tokens: Mutex::new(HashMap::new()),
+            machine_tokens: Mutex::new(HashMap::new()),
         }
     }
     pub async fn login(&self, ip: IpAddr, key: &str) -> Result<Token> {
@@ -62,4 +89,49 @@ impl Service {
             Ok(())
         }
     }
-} 
\ No newline at end of file
+
+    pub async fn client_login(&self, client_id: client::Id) -> Result<Token> {
+        let token = gen_token()?;
+        self.restore_client_token(hash_token(&token), client_id).await;
+        log::info!("new token created for participant [{client_id}]");
+        Ok(token)
+    }
+
+    pub async fn restore_client_token(&self, token_hash: u128, client_id: client::Id) {
+        self.client_tokens.lock().await.insert(token_hash, client_id);
+    }
+
+    pub async fn check_client_token(&self, token: &Token) -> Result<client::Id> {
+        self.client_tokens.lock().await.get(&hash_token(token)).copied().ok_or(OcjError::Auth(error::AuthError::IncorrectToken))
+    }
+
+    pub async fn issue_machine_token(&self, name: Box<str>) -> Result<Token> {
+        let mut machine_tokens = self.machine_tokens.lock().await;
+        if machine_tokens.values().any(|n| *n == name) {
+            return Err(error::AuthError::MachineAlreadyExists(name).into());
+        }
+        let token = gen_token()?;
+        log::info!("new token created for machine <{name}>");
+        machine_tokens.insert(hash_token(&token), name);
+        Ok(token)
+    }
+
+    pub async fn restore_machine_token(&self, token_hash: u128, name: Box<str>) {
+        self.machine_tokens.lock().await.insert(token_hash, name);
+    }
+
+    pub async fn revoke_machine_token(&self, name: &str) -> Result<()> {
+        let mut machine_tokens = self.machine_tokens.lock().await;
+        let count = machine_tokens.len();
+        machine_tokens.retain(|_, n| n.as_ref() != name);
+        if machine_tokens.len() == count {
+            return Err(error::AuthError::MachineNotFound(name.into()).into());
+        }
+        log::info!("token of machine <{name}> revoked");
+        Ok(())
+    }
+
+    pub async fn check_machine_token(&self, token: &Token) -> Result<Box<str>> {
+        self.machine_tokens.lock().await.get(&hash_token(token)).cloned().ok_or(OcjError::Auth(error::AuthError::IncorrectToken))
+    }
+}
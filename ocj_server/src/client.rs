@@ -1 +1,166 @@
-pub type Id = u32;
\ No newline at end of file
+use std::collections::{HashMap, HashSet};
+
+use serde::{Serialize, Deserialize};
+use tokio::sync::Mutex;
+
+use crate::{auth, config::{msg::client_to_server::auth::Login, participant::{Credentials, New}}, error::AuthError, Result};
+
+pub type Id = u32;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Participant {
+    pub id: Id,
+    pub login: Box<str>,
+    pub name: Box<str>,
+    // an argon2 PHC string
+    password_hash: Option<Box<str>>,
+    invite_code_hash: Option<u128>,
+}
+
+#[cfg(test)]
+impl Participant {
+    // a participant without credentials, the login is the name
+    pub fn new(id: Id, name: &str) -> Self {
+        Self { id, login: name.into(), name: name.into(), password_hash: None, invite_code_hash: None }
+    }
+}
+
+pub struct Service {
+    pub participants: Mutex<HashMap<Id, Participant>>,
+}
+
+impl Service {
+    pub fn init() -> Self {
+        Self {
+            participants: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn add(&self, new: New) -> Result<(Participant, Credentials)> {
+        Ok(self.add_all(vec![new]).await?.remove(0))
+    }
+
+    // either every participant of the batch is added or none of them
+    pub async fn add_all(&self, batch: Vec<New>) -> Result<Vec<(Participant, Credentials)>> {
+        for new in &batch {
+            let empty = if new.login.trim().is_empty() {
+                Some("login")
+            } else if new.name.trim().is_empty() {
+                Some("name")
+            } else if new.password.as_deref().is_some_and(str::is_empty) {
+                Some("password")
+            } else {
+                None
+            };
+            if let Some(field) = empty {
+                return Err(AuthError::EmptyField(field, new.login.clone()).into());
+            }
+        }
+
+        // hashing is slow, so it is done before the participants are locked
+        let mut password_hashes = Vec::with_capacity(batch.len());
+        for new in &batch {
+            password_hashes.push(match new.password.as_deref() {
+                Some(password) => Some(auth::hash_password(password).await?),
+                None => None,
+            });
+        }
+        let mut participants = self.participants.lock().await;
+        let mut logins: HashSet<&str> = participants.values().map(|p| &*p.login).collect();
+        if let Some(new) = batch.iter().find(|new| !logins.insert(&new.login)) {
+            return Err(AuthError::LoginAlreadyExists(new.login.clone()).into());
+        }
+
+        let mut invite_codes: Vec<Option<Box<str>>> = Vec::with_capacity(batch.len());
+        for new in &batch {
+            invite_codes.push(match new.password {
+                Some(_) => None,
+                None => Some(auth::gen_token()?.to_string().into()),
+            });
+        }
+        let first_id = participants.keys().max().map_or(1, |id| id + 1);
+        let mut added = Vec::with_capacity(batch.len());
+        for (i, ((new, password_hash), invite_code)) in batch.into_iter().zip(password_hashes).zip(invite_codes).enumerate() {
+            let participant = Participant {
+                id: first_id + i as Id,
+                login: new.login.clone(),
+                name: new.name,
+                password_hash,
+                invite_code_hash: invite_code.as_deref().map(auth::hash),
+            };
+            participants.insert(participant.id, participant.clone());
+            log::info!("new participant [{}]: {}", participant.id, participant.login);
+
+            added.push((participant, Credentials {
+                login: new.login,
+                invite_code,
+            }));
+        }
+        Ok(added)
+    }
+
+    pub async fn restore(&self, participant: Participant) {
+        self.participants.lock().await.insert(participant.id, participant);
+    }
+
+    pub async fn find(&self, login: &Login) -> Result<Id> {
+        let id = match login {
+            Login::Password { login, password } => {
+                let participant = self.participants.lock().await.values()
+                    .find(|p| p.login == *login)
+                    .and_then(|p| Some((p.id, p.password_hash.clone()?)));
+                match participant {
+                    Some((id, hash)) if auth::verify_password(password, &hash).await => Some(id),
+                    _ => None,
+                }
+            }
+            Login::InviteCode(code) => {
+                let hash = auth::hash(code);
+                self.participants.lock().await.values().find(|p| p.invite_code_hash == Some(hash)).map(|p| p.id)
+            }
+        };
+        id.ok_or(AuthError::IncorrectLogin.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OcjError;
+
+    fn new(login: &str, name: &str, password: Option<&str>) -> New {
+        New { login: login.into(), name: name.into(), password: password.map(Into::into) }
+    }
+
+    async fn logins(service: &Service) -> Vec<Box<str>> {
+        let mut logins: Vec<Box<str>> = service.participants.lock().await.values().map(|p| p.login.clone()).collect();
+        logins.sort();
+        logins
+    }
+
+    #[tokio::test]
+    async fn batch_is_added_whole_or_not_at_all() {
+        let service = Service::init();
+        service.add(new("alice", "Alice", Some("secret"))).await.unwrap();
+
+        for (batch, error) in [
+            (vec![new("bob", "Bob", None), new("alice", "Alice", None)], "login <alice> already exists"),
+            (vec![new("bob", "Bob", None), new("bob", "Bobby", None)], "login <bob> already exists"),
+            (vec![new("bob", "Bob", None), new("carol", " ", None)], "empty name of participant <carol>"),
+            (vec![new("bob", "Bob", None), new("", "Carol", None)], "empty login of participant <>"),
+            (vec![new("bob", "Bob", None), new("carol", "Carol", Some(""))], "empty password of participant <carol>"),
+        ] {
+            match service.add_all(batch).await {
+                Err(OcjError::Auth(e)) => assert_eq!(e.to_string(), error),
+                r => panic!("unexpected result {:?}", r.map(|added| added.len())),
+            }
+            assert_eq!(logins(&service).await, ["alice".into()]);
+        }
+
+        let added = service.add_all(vec![new("bob", "Bob", None), new("carol", "Carol", Some("pass"))]).await.unwrap();
+        assert_eq!(added.iter().map(|(p, _)| p.id).collect::<Vec<_>>(), [2, 3]);
+        assert!(added[0].1.invite_code.is_some() && added[1].1.invite_code.is_none());
+        assert_eq!(logins(&service).await, ["alice".into(), "bob".into(), "carol".into()]);
+        assert_eq!(service.find(&Login::Password { login: "carol".into(), password: "pass".into() }).await.unwrap(), 3);
+    }
+}
@@ -28,6 +28,124 @@ impl Service {
         }
     }
 
+    pub async fn check_going(&self) -> Result<()> {
+        match *self.contest.lock().await {
+            State::Going {..} => Ok(()),
+            State::InDevelop => Err(ContestError::StillInDevelop.into()),
+            State::Ready {..} => Err(ContestError::NotStartedYet.into()),
+            State::Paused {..} => Err(ContestError::Paused.into()),
+            State::Finished {..} => Err(ContestError::AlreadyFinished.into()),
+        }
+    }
+
+    pub async fn check_started(&self) -> Result<()> {
+        match *self.contest.lock().await {
+            State::Going {..} | State::Paused {..} | State::Finished {..} => Ok(()),
+            State::InDevelop => Err(ContestError::StillInDevelop.into()),
+            State::Ready {..} => Err(ContestError::NotStartedYet.into()),
+        }
+    }
+
+    pub async fn time(&self) -> Option<Time> {
+        match &*self.contest.lock().await {
+            State::Ready { time, .. } | State::Going { time, .. } | State::Paused { time, .. } | State::Finished { time } => Some(time.clone()),
+            State::InDevelop => None,
+        }
+    }
+
+    pub async fn start(&self, app: Weak<App>) -> Result<()> {
+        let mut contest = self.contest.lock().await;
+        let time = match contest.deref_mut() {
+            State::Ready { time, .. } => time.clone(),
+            State::InDevelop => return Err(ContestError::StillInDevelop.into()),
+            State::Going {..} | State::Paused {..} => return Err(ContestError::AlreadyGoing.into()),
+            State::Finished {..} => return Err(ContestError::AlreadyFinished.into()),
+        };
+        *contest = State::Going {
+            finisher: time.duration.map(|d| tokio::spawn(Self::finisher(app, time.start + d))),
+            time,
+        };
+        log::info!("contest state: GOING");
+        Ok(())
+    }
+
+    pub async fn finish(&self) -> Result<()> {
+        let mut contest = self.contest.lock().await;
+        let time = match contest.deref_mut() {
+            State::Going { time, .. } | State::Paused { time, .. } => time.clone(),
+            State::InDevelop => return Err(ContestError::StillInDevelop.into()),
+            State::Ready {..} => return Err(ContestError::NotStartedYet.into()),
+            State::Finished {..} => return Err(ContestError::AlreadyFinished.into()),
+        };
+        *contest = State::Finished { time };
+        log::info!("contest state: FINISHED");
+        Ok(())
+    }
+
+    pub async fn stop_finisher(&self) {
+        if let State::Going { finisher, .. } = self.contest.lock().await.deref_mut() {
+            if let Some(f) = finisher.take() {
+                f.abort();
+            }
+        }
+    }
+
+    pub async fn pause(&self, since: SystemTime) -> Result<()> {
+        let mut contest = self.contest.lock().await;
+        let time = match contest.deref_mut() {
+            State::Going { time, finisher } => {
+                if let Some(f) = finisher.take() {
+                    f.abort();
+                }
+                time.clone()
+            }
+            State::InDevelop => return Err(ContestError::StillInDevelop.into()),
+            State::Ready {..} => return Err(ContestError::NotStartedYet.into()),
+            State::Paused {..} => return Err(ContestError::Paused.into()),
+            State::Finished {..} => return Err(ContestError::AlreadyFinished.into()),
+        };
+        *contest = State::Paused { time, since };
+        log::info!("contest state: PAUSED");
+        Ok(())
+    }
+
+    pub async fn resume(&self, app: Weak<App>) -> Result<Time> {
+        let mut contest = self.contest.lock().await;
+        let time = match contest.deref_mut() {
+            State::Paused { time, since } => {
+                let paused = SystemTime::now().duration_since(*since).unwrap_or(Duration::ZERO);
+                Time {
+                    duration: time.duration.map(|d| d + paused),
+                    ..*time
+                }
+            }
+            State::InDevelop => return Err(ContestError::StillInDevelop.into()),
+            State::Ready {..} => return Err(ContestError::NotStartedYet.into()),
+            State::Going {..} => return Err(ContestError::NotPaused.into()),
+            State::Finished {..} => return Err(ContestError::AlreadyFinished.into()),
+        };
+        *contest = State::Going {
+            finisher: time.duration.map(|d| tokio::spawn(Self::finisher(app, time.start + d))),
+            time: time.clone(),
+        };
+        log::info!("contest state: GOING");
+        Ok(time)
+    }
+
+    pub async fn restore(&self, time: Time, paused: Option<SystemTime>, finished: bool, app: Weak<App>) -> Result<()> {
+        if finished {
+            *self.contest.lock().await = State::Finished { time };
+            log::info!("contest state: FINISHED");
+            Ok(())
+        } else if let Some(since) = paused {
+            *self.contest.lock().await = State::Paused { time, since };
+            log::info!("contest state: PAUSED");
+            Ok(())
+        } else {
+            self.ready(&time, app).await
+        }
+    }
+
     pub async fn update_start_time (&self, start_time: std::time::SystemTime, app: Weak<App>) -> Result<()> {
         let mut contest = self.contest.lock().await;
         match contest.deref_mut() {
@@ -54,7 +172,7 @@ impl Service {
         let app = Arc::downgrade(&app);
         let contest = contest.deref_mut();
         match contest {
-            State::Ready {time, ..} | State::Going {time, ..}=> {
+            State::Ready {time, ..} | State::Going {time, ..} | State::Paused {time, ..} => {
                 let prev_duration = time.duration;
                 *time = Time {
                     duration: match op {
@@ -62,7 +180,7 @@ impl Service {
                             prev_duration.map(|d| d + dur)
                         }
                         UpdateDuration::Sub(dur) => {
-                            prev_duration.map(|d| d - dur)
+                            prev_duration.map(|d| d.saturating_sub(dur))
                         }
                         UpdateDuration::Set(dur) => dur,
                     },
@@ -73,11 +191,14 @@ impl Service {
             State::InDevelop => {
                 Err(ContestError::StillInDevelop.into())
             }
-            _ => {
-                Err(ContestError::AlreadyGoing.into())
+            State::Finished {..} => {
+                Err(ContestError::AlreadyFinished.into())
             }
         }?;
         if let State::Going { time, finisher } = contest {
+            if let Some(f) = finisher.take() {
+                f.abort();
+            }
             if let Some (d) = time.duration {
                 *finisher = Some(tokio::spawn(Self::finisher(app, time.start + d)));
             }
@@ -115,5 +236,11 @@ pub enum State {
         time: Time,
         finisher: Option<JoinHandle<()>>,
     },
-    Finished,
+    Paused {
+        time: Time,
+        since: SystemTime,
+    },
+    Finished {
+        time: Time,
+    },
 }
\ No newline at end of file
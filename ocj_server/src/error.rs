@@ -11,6 +11,21 @@ pub enum AuthError {
 
     #[error("incorrect key")]
     IncorrectToken,
+
+    #[error("incorrect login or password")]
+    IncorrectLogin,
+
+    #[error("login <{0}> already exists")]
+    LoginAlreadyExists(Box<str>),
+
+    #[error("empty {0} of participant <{1}>")]
+    EmptyField(&'static str, Box<str>),
+
+    #[error("machine <{0}> already has a token")]
+    MachineAlreadyExists(Box<str>),
+
+    #[error("machine <{0}> not found")]
+    MachineNotFound(Box<str>),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -29,12 +44,27 @@ pub enum ContestError {
 
     #[error("already finished")]
     AlreadyFinished,
+
+    #[error("paused")]
+    Paused,
+
+    #[error("not paused")]
+    NotPaused,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum OcjError {
-    #[error("not a single machine was found")]
-    NoneMachineFound,
+    #[error("solution [{0}] not found")]
+    SolutionNotFound(ocj_config::solution::Id),
+
+    #[error("language <{0}> is not supported")]
+    UnsupportedLang(Box<str>),
+
+    #[error("problem {0} package is broken")]
+    BrokenPackage(ocj_config::solution::ProblemNum),
+
+    #[error("storage record {0} is broken: {1:?}")]
+    BrokenRecord(usize, serde_json::Error),
 
     #[error("local ip address error: {0:?}")]
     LocalIpAddress(#[from] local_ip_address::Error),
@@ -57,9 +87,15 @@ pub enum OcjError {
     #[error("try rng core error {0:?}")]
     RngCore(<OsRng as TryRngCore>::Error),
 
+    #[error("password hash error {0:?}")]
+    PasswordHash(argon2::password_hash::Error),
+
     #[error("contest error {0:?}")]
     Contest(#[from] ContestError),
 
+    #[error("json error {0:?}")]
+    Json(#[from] serde_json::Error),
+
     #[error("system time error {0:?}")]
     SystemTime(#[from] std::time::SystemTimeError),
 }
@@ -8,6 +8,12 @@ pub async fn update_tests(data: &[u8]) -> Result<()>{
     Ok(())
 }
 
+pub async fn update_statements(data: &[u8]) -> Result<()>{
+    let mut file= File::create(format!("{}.tar.gz", config::STATEMENTS)).await?;
+    file.write_all(data).await?;
+    Ok(())
+}
+
 pub async fn get_tests() -> Result<File> {
     Ok(File::open(format!("{}.tar.gz", config::TESTS)).await?)
 }
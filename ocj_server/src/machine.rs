@@ -1,19 +1,25 @@
-use std::{collections::{BTreeSet, HashMap}, sync::Arc};
+use std::{cmp::Reverse, collections::{BTreeMap, BTreeSet, HashMap}, sync::{Arc, Weak}, time::Instant};
 
-use ocj_config::contest::File;
-use tokio::{io::AsyncReadExt, sync::{mpsc::{UnboundedReceiver, UnboundedSender}, Mutex}, task::JoinHandle};
+use ocj_config::{contest::File, machine::{Health, Info}, solution::{Lang, LangInfo, ProblemNum}, tests::PackageError};
+use tokio::{io::AsyncReadExt, sync::{mpsc::{UnboundedReceiver, UnboundedSender}, Mutex, Notify}, task::JoinHandle};
 
-use crate::{config, solution::Solution, file, Result};
+use crate::{config, solution::Solution, file, App, OcjError, Result};
 
-pub type Id = u16;
+pub type Id = config::machine::Id;
 pub type InputMsg = config::msg::MachineToServer;
 pub type OutputMsg = config::msg::ServerToMachine;
 
 pub struct Service {
     pub machines: Mutex<HashMap<Id, Arc<Machine>>>,
-    pub machines_tasks_counters: Mutex<BTreeSet<(usize, Id)>>,
 
     pub machine_handles: Mutex<HashMap<Id, JoinHandle<()>>>,
+
+    // reported by every machine after the last tests update, a problem is broken if any machine failed to prepare it
+    package_errors: Mutex<BTreeMap<Id, BTreeMap<ProblemNum, Box<str>>>>,
+    // every language some machine has advertised
+    langs: Mutex<BTreeMap<Lang, LangInfo>>,
+    // token name of every machine id that has connected, another token can't take the id over
+    owners: Mutex<HashMap<Id, Box<str>>>,
 }
 
 
@@ -22,32 +28,151 @@ impl Service {
         Self {
             machines: Mutex::new(HashMap::new()),
             machine_handles: Mutex::new(HashMap::new()),
-            machines_tasks_counters: Mutex::new(BTreeSet::new()),
+            package_errors: Mutex::new(BTreeMap::new()),
+            langs: Mutex::new(BTreeMap::new()),
+            owners: Mutex::new(HashMap::new()),
         }
     }
 
-    pub async fn add_machine(&self, machine: Machine) {
+    // false if the id belongs to a machine with another token
+    pub async fn claim_id(&self, id: Id, name: &str) -> bool {
+        self.owners.lock().await.entry(id).or_insert_with(|| name.into()).as_ref() == name
+    }
+
+    pub async fn add_machine(&self, machine: Arc<Machine>, app: Weak<App>) {
         let id = machine.id;
-        let machine = Arc::new(machine);
         self.machines.lock().await.insert(id, machine.clone());
-        self.machines_tasks_counters.lock().await.insert((0, id));
-        
-        self.machine_handles.lock().await.insert(id, Machine::handle(machine));
+        self.machine_handles.lock().await.insert(id, Machine::handle(machine, app));
+    }
+
+    pub async fn get(&self, id: Id) -> Option<Arc<Machine>> {
+        self.machines.lock().await.get(&id).cloned()
     }
 
-    pub async fn remove_machine(&self, id: Id) {
+    // false if the machine has already been replaced by its new connection
+    pub async fn remove_machine(&self, machine: &Arc<Machine>) -> bool {
+        let id = machine.id;
         let mut machines = self.machines.lock().await;
-        let mut machines_tasks_counters = self.machines_tasks_counters.lock().await;
-        let tasks_count = *machines.get(&id).unwrap().tasks_count.lock().await;
+        if !machines.get(&id).is_some_and(|m| Arc::ptr_eq(m, machine)) {
+            return false;
+        }
         machines.remove(&id);
         self.machine_handles.lock().await.remove(&id);
-        machines_tasks_counters.remove(&(tasks_count, id));
+        self.package_errors.lock().await.remove(&id);
+        true
+    }
+
+    pub async fn infos(&self) -> Vec<Info> {
+        let machines = self.machines.lock().await;
+        let mut infos = Vec::new();
+        for machine in machines.values() {
+            infos.push(Info {
+                id: machine.id,
+                name: machine.name.clone(),
+                langs: machine.langs.lock().await.iter().cloned().collect(),
+                slots: machine.slots,
+                tasks: *machine.tasks_count.lock().await,
+                health: machine.health.lock().await.clone(),
+                last_heartbeat: machine.last_heartbeat.lock().await.elapsed().as_secs(),
+            });
+        }
+        infos.sort_by_key(|i| i.id);
+        infos
+    }
+
+    // the machine with the most free slots supporting the language gets the solution,
+    // none if every such machine is busy
+    pub async fn send_solution(&self, solution: &Solution) -> Option<Id> {
+        let machines = self.machines.lock().await;
+        let mut candidates = Vec::new();
+        for (id, machine) in machines.iter() {
+            let free = (machine.slots as usize).saturating_sub(*machine.tasks_count.lock().await);
+            if free > 0 && machine.langs.lock().await.contains(&solution.lang) {
+                candidates.push((free, Reverse(*id)));
+            }
+        }
+        let (_, Reverse(id)) = candidates.into_iter().max()?;
+        if !machines[&id].send_solution(solution) {
+            return None;
+        }
+        *machines[&id].tasks_count.lock().await += 1;
+        Some(id)
+    }
+
+    pub async fn task_done(&self, id: Id) {
+        let machines = self.machines.lock().await;
+        let machine = if let Some(m) = machines.get(&id) {m} else {
+            log::warn!("machine [{id}] was removed before its task was done");
+            return;
+        };
+        let mut tasks_count = machine.tasks_count.lock().await;
+        if *tasks_count == 0 {
+            log::warn!("machine [{id}] has no tasks to be done");
+            return;
+        }
+        *tasks_count -= 1;
     }
 
     pub async fn broadcast_update_tests(&self, data: &File) {
+        self.package_errors.lock().await.clear();
         self.broadcast(OutputMsg::UpdateTests(Box::from(data))).await;
     }
 
+    pub async fn set_package_errors(&self, id: Id, errors: Vec<PackageError>) {
+        let mut machine_errors = BTreeMap::new();
+        for e in errors {
+            log::error!("machine [{id}] problem {} package error: {}", e.problem_number, e.error);
+            machine_errors.insert(e.problem_number, e.error);
+        }
+        self.package_errors.lock().await.insert(id, machine_errors);
+    }
+
+    // one error for every broken problem, from the machine with the lowest id
+    pub async fn package_errors(&self) -> Vec<PackageError> {
+        let mut merged = BTreeMap::new();
+        for (n, e) in self.package_errors.lock().await.values().flatten() {
+            merged.entry(*n).or_insert_with(|| e.clone());
+        }
+        merged.into_iter().map(|(problem_number, error)| PackageError { problem_number, error }).collect()
+    }
+
+    pub async fn add_langs(&self, id: Id, langs: &[LangInfo]) {
+        let mut known = self.langs.lock().await;
+        for info in langs {
+            known.insert(info.lang.clone(), info.clone());
+        }
+        log::info!("machine [{id}] languages: {:?}", langs.iter().map(|l| &l.lang.0).collect::<Vec<_>>());
+    }
+
+    pub async fn langs(&self) -> Vec<LangInfo> {
+        self.langs.lock().await.values().cloned().collect()
+    }
+
+    pub async fn check_lang(&self, lang: &Lang) -> Result<()> {
+        let langs = self.langs.lock().await;
+        // before any machine connects the submission just waits in the queue
+        if langs.is_empty() || langs.contains_key(lang) {
+            Ok(())
+        } else {
+            Err(OcjError::UnsupportedLang(lang.0.clone()))
+        }
+    }
+
+    pub async fn check_package(&self, problem_number: ProblemNum) -> Result<()> {
+        if self.package_errors.lock().await.values().any(|errors| errors.contains_key(&problem_number)) {
+            Err(OcjError::BrokenPackage(problem_number))
+        } else {
+            Ok(())
+        }
+    }
+
+    // the connections of the machines are closed, their work goes back to the queue
+    pub async fn revoke(&self, name: &str) {
+        for machine in self.machines.lock().await.values().filter(|m| m.name.as_ref() == name) {
+            machine.revoked.notify_one();
+        }
+    }
+
     pub async fn broadcast(&self, msg: OutputMsg) {
         let machines = self.machines.lock().await;
         for (_, machine) in &*machines {
@@ -58,19 +183,39 @@ impl Service {
 
 pub struct Machine {
     pub id: Id,
+    pub name: Box<str>,
+    // solutions judged at once
+    pub slots: u16,
     pub tasks_count: Mutex<usize>,
+    pub langs: Mutex<BTreeSet<Lang>>,
+    // any message counts as a heartbeat
+    pub last_heartbeat: Mutex<Instant>,
+    pub health: Mutex<Option<Health>>,
+    pub revoked: Notify,
 
     ws_sender: UnboundedSender<OutputMsg>,
     ws_receiver: Mutex<UnboundedReceiver<InputMsg>>,
 }
 
 impl Machine {
-    pub fn new(id: Id, ws_sender: UnboundedSender<OutputMsg>, ws_receiver: UnboundedReceiver<InputMsg>) -> Self {
-        Self {id, tasks_count: Mutex::new(0), ws_sender, ws_receiver: Mutex::new(ws_receiver)}
+    pub fn new(id: Id, name: Box<str>, slots: u16, ws_sender: UnboundedSender<OutputMsg>, ws_receiver: UnboundedReceiver<InputMsg>) -> Self {
+        Self {
+            id,
+            name,
+            slots,
+            tasks_count: Mutex::new(0),
+            langs: Mutex::new(BTreeSet::new()),
+            last_heartbeat: Mutex::new(Instant::now()),
+            health: Mutex::new(None),
+            revoked: Notify::new(),
+            ws_sender,
+            ws_receiver: Mutex::new(ws_receiver),
+        }
     }
 
-    pub fn send_solution(&self, solution: &Solution) {
-        self.ws_sender.send(OutputMsg::JudgeSolution(solution.clone())).unwrap();
+    // false if the connection is already closed
+    pub fn send_solution(&self, solution: &Solution) -> bool {
+        self.ws_sender.send(OutputMsg::JudgeSolution(solution.clone())).is_ok()
     }
 
     pub fn send_tests(&self, file: Box<File>) {
@@ -88,18 +233,69 @@ impl Machine {
         Ok(())
     }
 
-    pub fn handle(self: Arc<Self>) -> JoinHandle<()> {
+    pub fn handle(self: Arc<Self>, app: Weak<App>) -> JoinHandle<()> {
         tokio::spawn(async move {
             while let Some(msg) = self.ws_receiver.lock().await.recv().await {
+                *self.last_heartbeat.lock().await = Instant::now();
                 match msg {
-                    InputMsg::Init => {
-                        if let Err(e) = self.init().await {
+                    InputMsg::Init { .. } => {
+                        log::warn!("machine [{}] sent init message again", self.id);
+                    },
+                    InputMsg::Heartbeat(health) => {
+                        log::debug!("machine [{}] heartbeat: {health:?}", self.id);
+                        *self.health.lock().await = Some(health);
+                    },
+                    InputMsg::TestsUpdated(errors) => {
+                        let app = if let Some(app) = app.upgrade() {app} else {
+                            break;
+                        };
+                        app.machine.set_package_errors(self.id, errors).await;
+                    },
+                    InputMsg::JudgeResult(result) => {
+                        let app = if let Some(app) = app.upgrade() {app} else {
+                            break;
+                        };
+                        if let Err(e) = app.judge_result(self.id, result).await {
+                            log::error!("{e}");
+                        }
+                    },
+                    InputMsg::Progress { solution_id, progress } => {
+                        let app = if let Some(app) = app.upgrade() {app} else {
+                            break;
+                        };
+                        if let Err(e) = app.submission.set_progress(solution_id, Some(progress)).await {
                             log::error!("{e}");
                         }
                     },
-                    _ => ()
                 }
             };
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(problems: &[ProblemNum]) -> Vec<PackageError> {
+        problems.iter().map(|n| PackageError { problem_number: *n, error: format!("problem {n} is broken").into() }).collect()
+    }
+
+    #[tokio::test]
+    async fn package_errors_are_kept_per_machine() {
+        let service = Service::init();
+        service.set_package_errors(1, errors(&[2])).await;
+        // a machine without errors doesn't hide the errors of another one
+        service.set_package_errors(2, Vec::new()).await;
+        assert!(service.check_package(2).await.is_err());
+        assert!(service.check_package(1).await.is_ok());
+
+        service.set_package_errors(2, errors(&[1, 2])).await;
+        assert_eq!(service.package_errors().await.iter().map(|e| e.problem_number).collect::<Vec<_>>(), [1, 2]);
+
+        // a new report of a machine replaces its previous one
+        service.set_package_errors(1, Vec::new()).await;
+        service.set_package_errors(2, Vec::new()).await;
+        assert!(service.package_errors().await.is_empty());
+    }
+}
@@ -5,28 +5,40 @@ mod machine;
 mod error;
 mod file;
 mod contest_time;
+mod submission;
+mod storage;
+mod scoreboard;
+mod queue;
 
 use error::{OcjError, Result};
-use ocj_config::{self as config, solution::Lang};
-use tokio::sync::Mutex;
+use ocj_config as config;
 
-use std::{collections::HashMap, net::IpAddr, sync::Arc, time::Duration};
+use std::{net::IpAddr, path::PathBuf, sync::Arc, time::SystemTime};
+use tokio::{io::AsyncReadExt, sync::broadcast};
 
-use config::solution::{self, Solution};
+use config::{auth::Token, contest::{Time, UpdateTime}, machine::HEARTBEAT_TIMEOUT, msg::{client_to_server::{auth::Login, submissions::Submit}, ServerToClientEvent}, participant::{Credentials, New}, scoreboard::{Rules, Scoreboard, Unfreeze}, solution::{self, JudgeResult, Solution}};
+use storage::Record;
 
 struct App {
     ip: IpAddr,
 
     auth: auth::Service,
+    client: client::Service,
     machine: machine::Service,
     contest_time: contest_time::Service,
-    solutions: Mutex<HashMap<solution::Id, client::Id>>,
+    submission: submission::Service,
+    scoreboard: scoreboard::Service,
+    storage: storage::Service,
+    queue: queue::Service,
+
+    events: broadcast::Sender<ServerToClientEvent>,
 }
 
 impl App {
-    pub async fn init(auth: auth::Service, machine: machine::Service, contest_time: contest_time::Service) -> Result<Self> {
+    pub async fn init(auth: auth::Service, client: client::Service, machine: machine::Service, contest_time: contest_time::Service, submission: submission::Service, scoreboard: scoreboard::Service, storage: storage::Service) -> Result<Self> {
         Ok(Self {
             auth,
+            client,
             ip: {
                 let ip = local_ip_address::local_ip()?;
                 println!("server ip: {}", ip);
@@ -34,25 +46,277 @@ impl App {
             },
             machine,
             contest_time,
-
-            solutions: Mutex::new(HashMap::new()),
+            submission,
+            scoreboard,
+            storage,
+            queue: queue::Service::init(),
+            events: broadcast::channel(16).0,
         })
     }
 
-    pub async fn judge(&self, solution: &Solution) -> Result<()> {  
-        let id = {
-            let mut machines_tasks_counters = self.machine.machines_tasks_counters.lock().await;
-            let mut machines = self.machine.machines.lock().await;
-            let (count, id) = *machines_tasks_counters.first().ok_or(OcjError::NoneMachineFound)?;
+    pub async fn judge(&self, solution: Solution) {
+        self.queue.push(solution).await;
+        self.dispatch().await;
+    }
+
+    pub async fn dispatch(&self) {
+        self.queue.dispatch(&self.machine).await;
+    }
+
+    // a machine that reconnects with the same id replaces its old connection
+    // and keeps the solutions it still judges
+    pub async fn add_machine(self: &Arc<Self>, machine: machine::Machine, langs: Vec<solution::LangInfo>, judging: Vec<solution::Id>) -> Arc<machine::Machine> {
+        let id = machine.id;
+        if let Some(old) = self.machine.get(id).await {
+            log::warn!("machine [{id}] reconnected");
+            self.machine.remove_machine(&old).await;
+        }
+        log::info!("machine [{id}] connected with {} slots", machine.slots);
+        let machine = Arc::new(machine);
+        self.machine.add_langs(id, &langs).await;
+        *machine.langs.lock().await = langs.into_iter().map(|l| l.lang).collect();
+        let requeued = self.queue.connect(&self.machine, machine.clone(), &judging, Arc::downgrade(self)).await;
+        self.requeued(id, requeued).await;
+        if let Err(e) = machine.init().await {
+            log::error!("{e}");
+        }
+        self.dispatch().await;
+        machine
+    }
+
+    // a silent or revoked machine loses its solutions at once
+    pub async fn remove_machine(&self, machine: &Arc<machine::Machine>) {
+        if !self.machine.remove_machine(machine).await {
+            return;
+        }
+        let id = machine.id;
+        log::info!("machine [{id}] disconnected");
+        let requeued = self.queue.requeue_machine(id).await;
+        self.requeued(id, requeued).await;
+        self.dispatch().await;
+    }
+
+    // a machine whose connection is closed may reconnect and send the results,
+    // its solutions go back to the queue if it is not back in the heartbeat timeout
+    pub async fn disconnect_machine(self: &Arc<Self>, machine: &Arc<machine::Machine>) {
+        if !self.machine.remove_machine(machine).await {
+            return;
+        }
+        let id = machine.id;
+        log::info!("machine [{id}] disconnected, waiting {} s for it to reconnect", HEARTBEAT_TIMEOUT.as_secs());
+        let app = Arc::downgrade(self);
+        tokio::spawn(async move {
+            tokio::time::sleep(HEARTBEAT_TIMEOUT).await;
+            let app = if let Some(app) = app.upgrade() {app} else {
+                return;
+            };
+            let requeued = app.queue.requeue_lost(&app.machine, id).await;
+            if requeued.is_empty() {
+                return;
+            }
+            app.requeued(id, requeued).await;
+            app.dispatch().await;
+        });
+    }
+
+    async fn requeued(&self, id: machine::Id, solutions: Vec<solution::Id>) {
+        for solution_id in solutions {
+            log::warn!("machine [{id}] is lost, solution [{solution_id}] is back in the queue");
+            _ = self.submission.set_progress(solution_id, None).await;
+        }
+    }
+
+    pub async fn issue_machine_token(&self, name: Box<str>) -> Result<Token> {
+        let token = self.auth.issue_machine_token(name.clone()).await?;
+        self.storage.append(&Record::MachineToken { token_hash: auth::hash_token(&token), name }).await?;
+        Ok(token)
+    }
 
-            machines_tasks_counters.remove(&(count, id));
-            machines_tasks_counters.insert((count + 1, id));
-            *machines.get_mut(&id).unwrap().tasks_count.lock().await += 1;
-            id
+    pub async fn revoke_machine(&self, name: Box<str>) -> Result<()> {
+        self.auth.revoke_machine_token(&name).await?;
+        self.storage.append(&Record::MachineTokenRevoked(name.clone())).await?;
+        self.machine.revoke(&name).await;
+        Ok(())
+    }
+
+    pub async fn requeue_timed_out(&self) {
+        let timed_out = self.queue.requeue_timed_out().await;
+        if timed_out.is_empty() {
+            return;
+        }
+        for (solution_id, machine_id) in timed_out {
+            log::warn!("machine [{machine_id}] timed out on solution [{solution_id}], it is back in the queue");
+            self.machine.task_done(machine_id).await;
+            _ = self.submission.set_progress(solution_id, None).await;
+        }
+        self.dispatch().await;
+    }
+
+    pub async fn submit(&self, client_id: client::Id, submit: Submit) -> Result<solution::Id> {
+        self.contest_time.check_going().await?;
+        self.machine.check_package(submit.problem_number).await?;
+        self.machine.check_lang(&submit.lang).await?;
+
+        let submission = self.submission.add(client_id, submit).await;
+        log::info!("new submission [{}] by {client_id}", submission.solution.id);
+        self.storage.append(&Record::Submission(submission.clone())).await?;
+
+        let id = submission.solution.id;
+        self.judge(submission.solution).await;
+        Ok(id)
+    }
+
+    pub async fn judge_result(&self, machine_id: machine::Id, result: JudgeResult) -> Result<()> {
+        // the machine holding the solution may be another one if it was requeued
+        if let Some(holder) = self.queue.done(result.solution_id).await {
+            self.machine.task_done(holder).await;
+        }
+        self.dispatch().await;
+        if self.submission.is_judged(result.solution_id).await? {
+            log::warn!("solution [{}] is already judged, result of machine [{machine_id}] is ignored", result.solution_id);
+            return Ok(());
+        }
+        log::info!("solution [{}] judged by machine [{machine_id}]: {:?} {} ms {} KB", result.solution_id, result.verdict, result.time, result.memory);
+        let judged = submission::Judged {
+            time: SystemTime::now(),
+            result,
         };
+        self.storage.append(&Record::Judged(judged.clone())).await?;
+        self.submission.set_judged(judged).await
+    }
+
+    pub async fn add_participant(&self, new: New) -> Result<Credentials> {
+        let (participant, credentials) = self.client.add(new).await?;
+        self.storage.append(&Record::Participant(participant)).await?;
+        Ok(credentials)
+    }
 
-        self.machine.machines.lock().await.get_mut(&id).unwrap().send_solution(&solution);
+    pub async fn import_participants(&self, batch: Vec<New>) -> Result<Vec<Credentials>> {
+        let mut credentials = Vec::with_capacity(batch.len());
+        for (participant, c) in self.client.add_all(batch).await? {
+            self.storage.append(&Record::Participant(participant)).await?;
+            credentials.push(c);
+        }
+        Ok(credentials)
+    }
+
+    pub async fn client_login(&self, login: &Login) -> Result<Token> {
+        let client_id = self.client.find(login).await?;
+        let token = self.auth.client_login(client_id).await?;
+        self.storage.append(&Record::ClientToken { token_hash: auth::hash_token(&token), client_id }).await?;
+        Ok(token)
+    }
 
+    pub async fn set_rules(&self, rules: Rules) -> Result<()> {
+        self.scoreboard.set_rules(rules).await;
+        self.storage.append(&Record::ScoreboardRules(rules)).await
+    }
+
+    pub async fn unfreeze(&self, unfreeze: Unfreeze) -> Result<()> {
+        self.scoreboard.unfreeze(unfreeze).await;
+        self.storage.append(&Record::Unfreeze(unfreeze)).await
+    }
+
+    pub async fn scoreboard(&self, public: bool) -> Scoreboard {
+        let time = self.contest_time.time().await;
+        let participants = self.client.participants.lock().await;
+        let submissions = self.submission.submissions.lock().await;
+        self.scoreboard.build(time.as_ref(), public, &participants, &submissions).await
+    }
+
+    pub async fn submissions(&self, client_id: Option<client::Id>) -> Vec<solution::Info> {
+        let participants = self.client.participants.lock().await;
+        let submissions = self.submission.submissions.lock().await;
+        let mut infos: Vec<solution::Info> = submissions.values()
+            .filter(|s| client_id.is_none_or(|id| id == s.client_id))
+            .map(|s| s.info(participants.get(&s.client_id).map_or("".into(), |p| p.login.clone())))
+            .collect();
+        infos.sort_by_key(|i| i.id);
+        infos
+    }
+
+    pub async fn submission(&self, id: solution::Id, client_id: Option<client::Id>) -> Result<solution::Info> {
+        let participants = self.client.participants.lock().await;
+        let submissions = self.submission.submissions.lock().await;
+        let s = submissions.get(&id)
+            .filter(|s| client_id.is_none_or(|id| id == s.client_id))
+            .ok_or(OcjError::SolutionNotFound(id))?;
+        Ok(s.info(participants.get(&s.client_id).map_or("".into(), |p| p.login.clone())))
+    }
+
+    pub async fn ready_contest(self: &Arc<Self>, time: &Time) -> Result<()> {
+        self.contest_time.ready(time, Arc::downgrade(self)).await?;
+        self.storage.append(&Record::ContestTime(time.clone())).await
+    }
+
+    pub async fn update_contest_time(self: &Arc<Self>, update: UpdateTime) -> Result<()> {
+        match update {
+            UpdateTime::Start(start) => self.contest_time.update_start_time(start, Arc::downgrade(self)).await?,
+            UpdateTime::Duration(op) => self.contest_time.update_duration(op, self.clone()).await?,
+        }
+        if let Some(time) = self.contest_time.time().await {
+            log::info!("contest time updated: {time:?}");
+            self.storage.append(&Record::ContestTime(time)).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn pause_contest(&self) -> Result<()> {
+        let since = SystemTime::now();
+        self.contest_time.pause(since).await?;
+        self.storage.append(&Record::ContestPaused(since)).await?;
+        self.notify(ServerToClientEvent::ContestPaused);
+        Ok(())
+    }
+
+    pub async fn resume_contest(self: &Arc<Self>) -> Result<()> {
+        let time = self.contest_time.resume(Arc::downgrade(self)).await?;
+        self.storage.append(&Record::ContestResumed).await?;
+        self.storage.append(&Record::ContestTime(time)).await?;
+        self.notify(ServerToClientEvent::ContestResumed);
+        Ok(())
+    }
+
+    pub async fn finish_contest_now(&self) -> Result<()> {
+        self.contest_time.stop_finisher().await;
+        self.finish_contest().await
+    }
+
+    pub async fn restore(self: &Arc<Self>, records: Vec<Record>) -> Result<()> {
+        let mut time = None;
+        let mut paused = None;
+        let mut finished = false;
+        for record in records {
+            match record {
+                Record::Submission(s) => self.submission.restore(s).await,
+                Record::Judged(j) => self.submission.set_judged(j).await?,
+                Record::ContestTime(t) => time = Some(t),
+                Record::ContestPaused(since) => paused = Some(since),
+                Record::ContestResumed => paused = None,
+                Record::ContestFinished => finished = true,
+                Record::ScoreboardRules(r) => self.scoreboard.set_rules(r).await,
+                Record::Unfreeze(u) => self.scoreboard.unfreeze(u).await,
+                Record::Participant(p) => self.client.restore(p).await,
+                Record::ClientToken { token_hash, client_id } => self.auth.restore_client_token(token_hash, client_id).await,
+                Record::MachineToken { token_hash, name } => self.auth.restore_machine_token(token_hash, name).await,
+                Record::MachineTokenRevoked(name) => self.auth.revoke_machine_token(&name).await?,
+            }
+        }
+
+        let not_judged: Vec<Solution> = self.submission.submissions.lock().await.values()
+            .filter(|s| s.judged.is_none())
+            .map(|s| s.solution.clone())
+            .collect();
+        if !not_judged.is_empty() {
+            log::warn!("{} restored submissions are not judged, they are queued", not_judged.len());
+        }
+        for solution in not_judged {
+            self.queue.push(solution).await;
+        }
+
+        if let Some(time) = time {
+            self.contest_time.restore(time, paused, finished, Arc::downgrade(self)).await?;
+        }
         Ok(())
     }
 
@@ -62,13 +326,33 @@ impl App {
         Ok(())
     }
 
-    pub async fn start_contest(&self) -> Result<()> {
-        log::info!("contest started");
+    pub async fn update_statements(&self, data: &[u8]) -> Result<()> {
+        file::update_statements(data).await
+    }
+
+    pub async fn statements(&self) -> Result<Vec<u8>> {
+        self.contest_time.check_started().await?;
+        let mut buf = Vec::new();
+        file::get_statements().await?.read_to_end(&mut buf).await?;
+        Ok(buf)
+    }
+
+    pub fn notify(&self, event: ServerToClientEvent) {
+        _ = self.events.send(event);
+    }
+
+    pub async fn start_contest(self: &Arc<Self>) -> Result<()> {
+        self.contest_time.start(Arc::downgrade(self)).await?;
+        log::info!("contest started, statements released");
+        self.notify(ServerToClientEvent::ContestStarted);
         Ok(())
     }
 
     pub async fn finish_contest(&self) -> Result<()> {
-        log::info!("contest finished");
+        self.contest_time.finish().await?;
+        self.storage.append(&Record::ContestFinished).await?;
+        log::info!("contest finished, submissions closed");
+        self.notify(ServerToClientEvent::ContestFinished);
         Ok(())
     }
 }
@@ -78,37 +362,19 @@ async fn main() -> Result<()> {
     env_logger::init();
     
     let key = std::env::args().nth(1).ok_or(OcjError::EnvArgsNotFound("key"))?;
+    let data_dir = PathBuf::from(std::env::args().nth(2).unwrap_or(storage::DIR.to_string()));
     
     let auth = auth::Service::init(&key);
+    let client = client::Service::init();
     let machine = machine::Service::init();
     let contest = contest_time::Service::init();
+    let submission = submission::Service::init();
+    let scoreboard = scoreboard::Service::init();
+    let (storage, records) = storage::Service::init(&data_dir).await?;
 
-    let app = Arc::new(App::init(auth, machine, contest).await?);
-    let app_clone = app.clone();
-    _ = tokio::spawn(async move {
-        tokio::time::sleep(Duration::from_secs(10)).await;
-        for i in 1..10 {
-            let r = app_clone.judge(&Solution { 
-                code: r#"
-                    #include<bits/stdc++.h>
-                    using namespace std;
-
-                    int main() {
-                        int a, b;
-                        cin >> a >> b;
-
-                        cout << a + b + 1 << '\n';
-                    }
-                "#.to_string(),
-                lang: Lang::Cpp,
-                problem_number: 1,
-                id: i,
-            }).await;
-            if let Err(e) = r {
-                log::error!("{e}");
-            }
-        }
-    });
+    let app = Arc::new(App::init(auth, client, machine, contest, submission, scoreboard, storage).await?);
+    app.restore(records).await?;
+    queue::Service::watch(Arc::downgrade(&app));
     server::run(&app).await?;
     Ok(())
 }
@@ -0,0 +1,121 @@
+use std::{collections::{BTreeMap, HashMap}, sync::{Arc, Weak}, time::{Duration, Instant}};
+
+use tokio::{sync::Mutex, task::JoinHandle};
+
+use crate::{config::solution::{self, Solution}, machine, App};
+
+// a machine that did not send a result in this time is thought to have lost the solution
+pub const JUDGE_TIMEOUT: Duration = Duration::from_secs(600);
+const WATCH_PERIOD: Duration = Duration::from_secs(10);
+
+struct Task {
+    machine_id: machine::Id,
+    solution: Solution,
+    sent: Instant,
+}
+
+pub struct Service {
+    // in submission order
+    waiting: Mutex<BTreeMap<solution::Id, Solution>>,
+    taken: Mutex<HashMap<solution::Id, Task>>,
+}
+
+impl Service {
+    pub fn init() -> Self {
+        Self {
+            waiting: Mutex::new(BTreeMap::new()),
+            taken: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn push(&self, solution: Solution) {
+        self.waiting.lock().await.insert(solution.id, solution);
+    }
+
+    // sends waiting solutions to machines supporting their languages, the rest keep waiting
+    pub async fn dispatch(&self, machines: &machine::Service) {
+        let mut waiting = self.waiting.lock().await;
+        let mut taken = self.taken.lock().await;
+        let ids: Vec<solution::Id> = waiting.keys().copied().collect();
+        for id in ids {
+            if let Some(machine_id) = machines.send_solution(&waiting[&id]).await {
+                log::info!("solution [{id}] sent to machine [{machine_id}]");
+                let solution = waiting.remove(&id).unwrap();
+                taken.insert(id, Task { machine_id, solution, sent: Instant::now() });
+            }
+        }
+        if !waiting.is_empty() {
+            log::info!("{} solutions are waiting for a machine", waiting.len());
+        }
+    }
+
+    // returns the machine that held the solution, none if it was not taken
+    pub async fn done(&self, id: solution::Id) -> Option<machine::Id> {
+        // a late result of a requeued solution is taken as well
+        self.waiting.lock().await.remove(&id);
+        self.taken.lock().await.remove(&id).map(|t| t.machine_id)
+    }
+
+    pub async fn requeue_machine(&self, machine_id: machine::Id) -> Vec<solution::Id> {
+        self.requeue(|t| t.machine_id == machine_id).await
+    }
+
+    // a machine that lost its connection gets its solutions back if it reconnects,
+    // it is added under the queue locks so requeue_lost sees either none or all of it
+    pub async fn connect(&self, machines: &machine::Service, machine: Arc<machine::Machine>, judging: &[solution::Id], app: Weak<App>) -> Vec<solution::Id> {
+        let mut waiting = self.waiting.lock().await;
+        let mut taken = self.taken.lock().await;
+        // the rest were lost with the connection
+        let ids = Self::move_back(&mut waiting, &mut taken, |t| t.machine_id == machine.id && !judging.contains(&t.solution.id));
+        *machine.tasks_count.lock().await = taken.values().filter(|t| t.machine_id == machine.id).count();
+        machines.add_machine(machine, app).await;
+        ids
+    }
+
+    // the solutions of a disconnected machine that did not come back
+    pub async fn requeue_lost(&self, machines: &machine::Service, machine_id: machine::Id) -> Vec<solution::Id> {
+        let mut waiting = self.waiting.lock().await;
+        let mut taken = self.taken.lock().await;
+        if machines.get(machine_id).await.is_some() {
+            return Vec::new();
+        }
+        Self::move_back(&mut waiting, &mut taken, |t| t.machine_id == machine_id)
+    }
+
+    pub async fn requeue_timed_out(&self) -> Vec<(solution::Id, machine::Id)> {
+        let timed_out: Vec<(solution::Id, machine::Id)> = self.taken.lock().await.iter()
+            .filter(|(_, t)| t.sent.elapsed() > JUDGE_TIMEOUT)
+            .map(|(id, t)| (*id, t.machine_id))
+            .collect();
+        self.requeue(|t| timed_out.iter().any(|(id, _)| *id == t.solution.id)).await;
+        timed_out
+    }
+
+    async fn requeue(&self, filter: impl Fn(&Task) -> bool) -> Vec<solution::Id> {
+        let mut waiting = self.waiting.lock().await;
+        let mut taken = self.taken.lock().await;
+        Self::move_back(&mut waiting, &mut taken, filter)
+    }
+
+    fn move_back(waiting: &mut BTreeMap<solution::Id, Solution>, taken: &mut HashMap<solution::Id, Task>, filter: impl Fn(&Task) -> bool) -> Vec<solution::Id> {
+        let ids: Vec<solution::Id> = taken.iter().filter(|(_, t)| filter(t)).map(|(id, _)| *id).collect();
+        for id in &ids {
+            let task = taken.remove(id).unwrap();
+            waiting.insert(*id, task.solution);
+        }
+        ids
+    }
+
+    pub fn watch(app: Weak<App>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(WATCH_PERIOD);
+            loop {
+                interval.tick().await;
+                let app = if let Some(app) = app.upgrade() {app} else {
+                    break;
+                };
+                app.requeue_timed_out().await;
+            }
+        })
+    }
+}
@@ -0,0 +1,239 @@
+use std::{cmp::Reverse, collections::{BTreeMap, BTreeSet, HashMap}, time::SystemTime};
+
+use tokio::sync::Mutex;
+
+use crate::{client::{self, Participant}, config::{contest::Time, scoreboard::{Problem, Row, Rules, Scoreboard, Unfreeze, ICPC_PENALTY_MINUTES}, solution::{self, ProblemNum, Verdict}}, submission::Submission};
+
+#[derive(Default)]
+struct Unfrozen {
+    all: bool,
+    problems: BTreeSet<ProblemNum>,
+}
+
+pub struct Frozen<'a> {
+    pub since: SystemTime,
+    pub unfrozen_problems: &'a BTreeSet<ProblemNum>,
+}
+
+pub struct Service {
+    rules: Mutex<Rules>,
+    unfrozen: Mutex<Unfrozen>,
+}
+
+impl Service {
+    pub fn init() -> Self {
+        Self {
+            rules: Mutex::new(Rules::default()),
+            unfrozen: Mutex::new(Unfrozen::default()),
+        }
+    }
+
+    pub async fn set_rules(&self, rules: Rules) {
+        *self.rules.lock().await = rules;
+        log::info!("scoreboard rules: {rules:?}");
+    }
+
+    pub async fn unfreeze(&self, unfreeze: Unfreeze) {
+        let mut unfrozen = self.unfrozen.lock().await;
+        match unfreeze {
+            Unfreeze::All => unfrozen.all = true,
+            Unfreeze::Problem(n) => _ = unfrozen.problems.insert(n),
+        }
+        log::info!("scoreboard unfreeze: {unfreeze:?}");
+    }
+
+    pub async fn build(&self, time: Option<&Time>, public: bool, participants: &HashMap<client::Id, Participant>, submissions: &HashMap<solution::Id, Submission>) -> Scoreboard {
+        let rules = *self.rules.lock().await;
+        let unfrozen = self.unfrozen.lock().await;
+        let frozen = match time.and_then(|t| t.freeze_time()) {
+            Some(since) if public && !unfrozen.all && since <= SystemTime::now() => Some(Frozen {
+                since,
+                unfrozen_problems: &unfrozen.problems,
+            }),
+            _ => None,
+        };
+        compute(rules, time.map(|t| t.start), frozen, participants, submissions)
+    }
+}
+
+pub fn compute(rules: Rules, start: Option<SystemTime>, frozen: Option<Frozen>, participants: &HashMap<client::Id, Participant>, submissions: &HashMap<solution::Id, Submission>) -> Scoreboard {
+    let mut submissions: Vec<&Submission> = submissions.values().collect();
+    submissions.sort_by_key(|s| (s.time, s.solution.id));
+
+    let problem_numbers: BTreeSet<ProblemNum> = submissions.iter().map(|s| s.solution.problem_number).collect();
+    let mut problems: HashMap<client::Id, BTreeMap<ProblemNum, Problem>> = participants.keys().map(|id| {
+        (*id, problem_numbers.iter().map(|n| (*n, Problem {
+            problem_number: *n,
+            solved: false,
+            wrong_attempts: 0,
+            pending: 0,
+            solved_minute: None,
+            score: 0,
+        })).collect())
+    }).collect();
+
+    for submission in submissions {
+        let problem = if let Some(p) = problems.get_mut(&submission.client_id).and_then(|p| p.get_mut(&submission.solution.problem_number)) {p} else {
+            continue;
+        };
+        let hidden = frozen.as_ref().is_some_and(|f| submission.time >= f.since && !f.unfrozen_problems.contains(&problem.problem_number));
+        let result = match &submission.judged {
+            Some(judged) if !hidden => &judged.result,
+            _ => {
+                if !problem.solved {
+                    problem.pending += 1;
+                }
+                continue;
+            }
+        };
+        problem.score = problem.score.max(result.score);
+        if problem.solved {
+            continue;
+        }
+        match result.verdict {
+            Verdict::Ok => {
+                problem.solved = true;
+                problem.solved_minute = Some(start.and_then(|s| submission.time.duration_since(s).ok()).map_or(0, |d| d.as_secs() / 60));
+            }
+            Verdict::Ce | Verdict::Fail | Verdict::Skipped => {}
+            _ => problem.wrong_attempts += 1,
+        }
+    }
+
+    let mut rows: Vec<Row> = participants.values().map(|participant| {
+        let problems: Vec<Problem> = problems.remove(&participant.id).unwrap_or_default().into_values().collect();
+        let solved = problems.iter().filter(|p| p.solved);
+        Row {
+            place: 0,
+            participant: participant.name.clone(),
+            solved: solved.clone().count() as u16,
+            penalty: solved.map(|p| p.solved_minute.unwrap_or(0) + ICPC_PENALTY_MINUTES * p.wrong_attempts as u64).sum(),
+            score: problems.iter().map(|p| p.score).sum(),
+            problems,
+        }
+    }).collect();
+
+    let rank = |row: &Row| match rules {
+        Rules::Icpc => (Reverse(row.solved as u32), row.penalty),
+        Rules::Ioi => (Reverse(row.score), 0),
+    };
+    rows.sort_by(|a, b| rank(a).cmp(&rank(b)).then_with(|| a.participant.cmp(&b.participant)));
+    for i in 0..rows.len() {
+        rows[i].place = if i > 0 && rank(&rows[i - 1]) == rank(&rows[i]) {
+            rows[i - 1].place
+        } else {
+            i + 1
+        };
+    }
+
+    Scoreboard { rules, frozen: frozen.is_some(), rows }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::{config::solution::{JudgeResult, Lang, Solution}, submission::Judged};
+
+    const START: Duration = Duration::from_secs(1_000_000);
+
+    fn participants(names: &[&str]) -> HashMap<client::Id, Participant> {
+        names.iter().enumerate().map(|(i, name)| (i as client::Id + 1, Participant::new(i as client::Id + 1, name))).collect()
+    }
+
+    // client, problem, minute, verdict and score or none if not judged yet
+    type Sent = (client::Id, ProblemNum, u64, Option<(Verdict, u32)>);
+
+    fn submissions(list: &[Sent]) -> HashMap<solution::Id, Submission> {
+        list.iter().enumerate().map(|(i, (client_id, problem_number, minute, judged))| {
+            let id = i as solution::Id;
+            let time = UNIX_EPOCH + START + Duration::from_secs(minute * 60);
+            (id, Submission {
+                client_id: *client_id,
+                solution: Solution { code: String::new(), lang: Lang("cpp".into()), problem_number: *problem_number, id },
+                time,
+                judged: judged.clone().map(|(verdict, score)| Judged {
+                    time,
+                    result: JudgeResult { solution_id: id, verdict, score, problem_number: *problem_number, time: 0, memory: 0, tests: Vec::new(), failed_test: None, groups: Vec::new(), compile_output: None },
+                }),
+                progress: None,
+            })
+        }).collect()
+    }
+
+    fn places(scoreboard: &Scoreboard) -> Vec<(usize, &str)> {
+        scoreboard.rows.iter().map(|r| (r.place, r.participant.as_ref())).collect()
+    }
+
+    #[test]
+    fn icpc_penalty_and_tied_places() {
+        let participants = participants(&["alice", "bob", "carol"]);
+        let submissions = submissions(&[
+            (1, 1, 5, Some((Verdict::Wa, 0))),
+            (1, 1, 10, Some((Verdict::Ok, 100))),
+            // attempts after the problem is solved are not counted
+            (1, 1, 15, Some((Verdict::Wa, 0))),
+            (1, 2, 20, Some((Verdict::Ok, 100))),
+            // compilation errors are free
+            (2, 2, 1, Some((Verdict::Ce, 0))),
+            (2, 2, 20, Some((Verdict::Ok, 100))),
+            (2, 1, 30, Some((Verdict::Ok, 100))),
+            (3, 1, 40, Some((Verdict::Tl, 0))),
+            (3, 2, 50, None),
+        ]);
+        let scoreboard = compute(Rules::Icpc, Some(UNIX_EPOCH + START), None, &participants, &submissions);
+        assert_eq!(places(&scoreboard), [(1, "alice"), (1, "bob"), (3, "carol")]);
+        assert_eq!(scoreboard.rows.iter().map(|r| (r.solved, r.penalty)).collect::<Vec<_>>(), [(2, 50), (2, 50), (0, 0)]);
+
+        let alice = &scoreboard.rows[0].problems[0];
+        assert_eq!((alice.wrong_attempts, alice.solved_minute), (1, Some(10)));
+        let carol = &scoreboard.rows[2].problems;
+        assert_eq!((carol[0].wrong_attempts, carol[1].wrong_attempts), (1, 0));
+        assert_eq!((carol[0].pending, carol[1].pending), (0, 1));
+        assert!(!scoreboard.frozen);
+    }
+
+    #[test]
+    fn freeze_hides_later_results() {
+        let participants = participants(&["alice", "bob"]);
+        let submissions = submissions(&[
+            (1, 1, 10, Some((Verdict::Ok, 100))),
+            (1, 2, 70, Some((Verdict::Ok, 100))),
+            (2, 1, 65, Some((Verdict::Wa, 0))),
+            (2, 1, 75, Some((Verdict::Ok, 100))),
+        ]);
+        let since = UNIX_EPOCH + START + Duration::from_secs(60 * 60);
+        let unfrozen_problems = BTreeSet::new();
+        let scoreboard = compute(Rules::Icpc, Some(UNIX_EPOCH + START), Some(Frozen { since, unfrozen_problems: &unfrozen_problems }), &participants, &submissions);
+        assert!(scoreboard.frozen);
+        assert_eq!(places(&scoreboard), [(1, "alice"), (2, "bob")]);
+        assert_eq!(scoreboard.rows.iter().map(|r| r.solved).collect::<Vec<_>>(), [1, 0]);
+        assert_eq!(scoreboard.rows[0].problems[1].pending, 1);
+        assert_eq!(scoreboard.rows[1].problems[0].pending, 2);
+
+        // unfreezing a problem reveals all of its results
+        let unfrozen_problems = BTreeSet::from([1]);
+        let scoreboard = compute(Rules::Icpc, Some(UNIX_EPOCH + START), Some(Frozen { since, unfrozen_problems: &unfrozen_problems }), &participants, &submissions);
+        assert_eq!(places(&scoreboard), [(1, "alice"), (2, "bob")]);
+        assert_eq!(scoreboard.rows.iter().map(|r| (r.solved, r.penalty)).collect::<Vec<_>>(), [(1, 10), (1, 95)]);
+        assert_eq!(scoreboard.rows[1].problems[0].pending, 0);
+        assert_eq!(scoreboard.rows[0].problems[1].pending, 1);
+    }
+
+    #[test]
+    fn ioi_best_score_per_problem() {
+        let participants = participants(&["alice", "bob", "carol"]);
+        let submissions = submissions(&[
+            (1, 1, 10, Some((Verdict::Wa, 60))),
+            (1, 1, 20, Some((Verdict::Wa, 30))),
+            (1, 2, 30, Some((Verdict::Ok, 100))),
+            (2, 1, 10, Some((Verdict::Ok, 100))),
+            (2, 2, 30, Some((Verdict::Wa, 60))),
+            (3, 1, 10, Some((Verdict::Wa, 10))),
+        ]);
+        let scoreboard = compute(Rules::Ioi, Some(UNIX_EPOCH + START), None, &participants, &submissions);
+        assert_eq!(places(&scoreboard), [(1, "alice"), (1, "bob"), (3, "carol")]);
+        assert_eq!(scoreboard.rows.iter().map(|r| r.score).collect::<Vec<_>>(), [160, 160, 10]);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{config, App};
 
-use axum::{extract::{ConnectInfo, Json, Request, State}, http::StatusCode, middleware::{self, Next}, response::{IntoResponse, Response}, routing::{get, patch, post}, Router};
+use axum::{extract::{ConnectInfo, Json, Path, Request, State}, http::StatusCode, middleware::{self, Next}, response::{IntoResponse, Response}, routing::{get, patch, post}, Router};
 use config::msg::admin_to_server as input_msg;
 use config::msg::ServerToAdmin as OutputMsg;
 use ocj_config::auth::Token;
@@ -38,12 +38,62 @@ mod contest {
                 Json::from(OutputMsg::Ok(()))
             }
         }
+
+        pub async fn errors(State(app): State<Arc<App>>) -> impl IntoResponse {
+            Json::from(OutputMsg::<_>::Ok(app.machine.package_errors().await))
+        }
+    }
+
+    pub mod statements {
+        use super::*;
+        pub async fn update(State(app): State<Arc<App>>, Json(msg): Json<input_msg::contest::statements::Update>) -> impl IntoResponse  {
+            if let Err(e) = app.update_statements(&msg).await {
+                Json::from(OutputMsg::<()>::Err(e.to_string().into()))
+            } else {
+                Json::from(OutputMsg::Ok(()))
+            }
+        }
     }
 
     pub mod state {
         use super::*;
         pub async fn set_ready(State(app): State<Arc<App>>, Json(msg): Json<input_msg::contest::state::SetReady>) -> impl IntoResponse  {            
-            if let Err(e) = app.contest_time.ready(&msg, Arc::downgrade(&app)).await {
+            if let Err(e) = app.ready_contest(&msg).await {
+                Json::from(OutputMsg::<()>::Err(e.to_string().into()))
+            } else {
+                Json::from(OutputMsg::Ok(()))
+            }
+        }
+
+        pub async fn finish(State(app): State<Arc<App>>) -> impl IntoResponse  {
+            if let Err(e) = app.finish_contest_now().await {
+                Json::from(OutputMsg::<()>::Err(e.to_string().into()))
+            } else {
+                Json::from(OutputMsg::Ok(()))
+            }
+        }
+
+        pub async fn pause(State(app): State<Arc<App>>) -> impl IntoResponse  {
+            if let Err(e) = app.pause_contest().await {
+                Json::from(OutputMsg::<()>::Err(e.to_string().into()))
+            } else {
+                Json::from(OutputMsg::Ok(()))
+            }
+        }
+
+        pub async fn resume(State(app): State<Arc<App>>) -> impl IntoResponse  {
+            if let Err(e) = app.resume_contest().await {
+                Json::from(OutputMsg::<()>::Err(e.to_string().into()))
+            } else {
+                Json::from(OutputMsg::Ok(()))
+            }
+        }
+    }
+
+    pub mod rules {
+        use super::*;
+        pub async fn set(State(app): State<Arc<App>>, Json(msg): Json<input_msg::contest::rules::Set>) -> impl IntoResponse  {
+            if let Err(e) = app.set_rules(msg).await {
                 Json::from(OutputMsg::<()>::Err(e.to_string().into()))
             } else {
                 Json::from(OutputMsg::Ok(()))
@@ -51,28 +101,88 @@ mod contest {
         }
     }
 
-    // pub mod time {
-    //     use super::*;
-    //     pub async fn update(State(app): State<Arc<App>>, ConnectInfo(ci): ConnectInfo<SocketAddr>, Json(msg): Json<input_msg::contest::tests::Update>) -> impl IntoResponse  {
-    //         let ip = ci.ip();
-    //         // let ip = std::net::IpAddr::V4(std::net::Ipv4Addr::new(, b, c, d));
-    //         let r = serde_json::to_string(&match app.auth.unwrap_secure::<Box<File>>(&ip, msg).await {
-    //             Ok(msg) => {
-    //                 if let Err(e) = app.update_tests(&msg).await {
-    //                     Err(e)
-    //                 } else {
-    //                     Ok(OutputMsg::PermissionGranted(()))
-    //                 }
-    //             },
-    //             Err(OcjError::Auth(_)) => {
-    //                 Ok(OutputMsg::PermissionDenied())
-    //             },
-    //             Err(e) => Err(e)
-    //         }.unwrap_or_else(|e| OutputMsg::InternalError(e.to_string().into()))).unwrap();
-    //         log::debug!("{r}");
-    //         r
-    //     }
-    // }
+    pub mod scoreboard {
+        use super::*;
+        pub async fn get(State(app): State<Arc<App>>) -> impl IntoResponse {
+            Json::from(OutputMsg::<_>::Ok(app.scoreboard(false).await))
+        }
+
+        pub async fn public(State(app): State<Arc<App>>) -> impl IntoResponse {
+            Json::from(OutputMsg::<_>::Ok(app.scoreboard(true).await))
+        }
+
+        pub async fn unfreeze(State(app): State<Arc<App>>, Json(msg): Json<input_msg::contest::scoreboard::Unfreeze>) -> impl IntoResponse {
+            if let Err(e) = app.unfreeze(msg).await {
+                Json::from(OutputMsg::<()>::Err(e.to_string().into()))
+            } else {
+                Json::from(OutputMsg::Ok(()))
+            }
+        }
+    }
+
+    pub mod time {
+        use super::*;
+        pub async fn update(State(app): State<Arc<App>>, Json(msg): Json<input_msg::contest::time::Update>) -> impl IntoResponse  {
+            if let Err(e) = app.update_contest_time(msg).await {
+                Json::from(OutputMsg::<()>::Err(e.to_string().into()))
+            } else {
+                Json::from(OutputMsg::Ok(()))
+            }
+        }
+    }
+}
+
+mod participants {
+    use super::*;
+    pub async fn add(State(app): State<Arc<App>>, Json(msg): Json<input_msg::participants::Add>) -> impl IntoResponse {
+        match app.add_participant(msg).await {
+            Ok(credentials) => Json::from(OutputMsg::Ok(credentials)),
+            Err(e) => Json::from(OutputMsg::Err(e.to_string().into())),
+        }
+    }
+
+    pub async fn import(State(app): State<Arc<App>>, Json(msg): Json<input_msg::participants::Import>) -> impl IntoResponse {
+        match app.import_participants(msg).await {
+            Ok(credentials) => Json::from(OutputMsg::Ok(credentials)),
+            Err(e) => Json::from(OutputMsg::Err(e.to_string().into())),
+        }
+    }
+}
+
+mod submissions {
+    use super::*;
+    use crate::config::solution;
+    pub async fn list(State(app): State<Arc<App>>) -> impl IntoResponse {
+        Json::from(OutputMsg::<_>::Ok(app.submissions(None).await))
+    }
+
+    pub async fn get(State(app): State<Arc<App>>, Path(id): Path<solution::Id>) -> impl IntoResponse {
+        match app.submission(id, None).await {
+            Ok(info) => Json::from(OutputMsg::Ok(info)),
+            Err(e) => Json::from(OutputMsg::Err(e.to_string().into())),
+        }
+    }
+}
+
+mod machines {
+    use super::*;
+    pub async fn list(State(app): State<Arc<App>>) -> impl IntoResponse {
+        Json::from(OutputMsg::<_>::Ok(app.machine.infos().await))
+    }
+
+    pub async fn issue_token(State(app): State<Arc<App>>, Json(name): Json<input_msg::machines::IssueToken>) -> impl IntoResponse {
+        match app.issue_machine_token(name).await {
+            Ok(token) => Json::from(OutputMsg::Ok(token)),
+            Err(e) => Json::from(OutputMsg::Err(e.to_string().into())),
+        }
+    }
+
+    pub async fn revoke(State(app): State<Arc<App>>, Json(name): Json<input_msg::machines::Revoke>) -> impl IntoResponse {
+        match app.revoke_machine(name).await {
+            Ok(()) => Json::from(OutputMsg::Ok(())),
+            Err(e) => Json::from(OutputMsg::Err(e.to_string().into())),
+        }
+    }
 }
 
 mod auth {
@@ -97,17 +207,47 @@ mod auth {
 pub fn router(app: Arc<App>) -> Router<()> {
     let contest: Router<_> = Router::new()
         .route("/tests", patch(contest::tests::update))
-        // .route("/time", patch(contest::time::update))
+        .route("/tests/errors", get(contest::tests::errors))
+        .route("/statements", patch(contest::statements::update))
+        .route("/rules", patch(contest::rules::set))
+        .nest("/scoreboard", Router::new()
+            .route("/", get(contest::scoreboard::get))
+            .route("/public", get(contest::scoreboard::public))
+            .route("/unfreeze", post(contest::scoreboard::unfreeze))
+        )
+        .route("/time", patch(contest::time::update))
         .nest("/state", Router::new()
             .route("/ready", post(contest::state::set_ready))
+            .route("/finish", post(contest::state::finish))
+            .route("/pause", post(contest::state::pause))
+            .route("/resume", post(contest::state::resume))
         )
         .layer(middleware::from_fn_with_state(app.clone(),auth_mw))
         .with_state(app.clone());
+    let participants: Router<_> = Router::new()
+        .route("/", post(participants::add))
+        .route("/import", post(participants::import))
+        .layer(middleware::from_fn_with_state(app.clone(),auth_mw))
+        .with_state(app.clone());
+    let submissions: Router<_> = Router::new()
+        .route("/", get(submissions::list))
+        .route("/{id}", get(submissions::get))
+        .layer(middleware::from_fn_with_state(app.clone(),auth_mw))
+        .with_state(app.clone());
+    let machines: Router<_> = Router::new()
+        .route("/", get(machines::list))
+        .route("/tokens", post(machines::issue_token))
+        .route("/revoke", post(machines::revoke))
+        .layer(middleware::from_fn_with_state(app.clone(),auth_mw))
+        .with_state(app.clone());
     let auth: Router<_> = Router::new()
         .route("/token", get(auth::token))
         .with_state(app.clone());
     Router::new()
         .nest("/contest", contest)
+        .nest("/participants", participants)
+        .nest("/submissions", submissions)
+        .nest("/machines", machines)
         .nest("/auth", auth)
         .with_state(app.clone())
 }
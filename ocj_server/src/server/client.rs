@@ -1,9 +1,127 @@
-use crate::{config, App, Result};
+use crate::{client, config, App, OcjError, Result};
 
-use axum::Router;
+use axum::{extract::{Extension, Json, Path, Request, State}, http::StatusCode, middleware::{self, Next}, response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Response}, routing::{get, post}, Router};
+use futures::Stream;
+use tokio::sync::broadcast::error::RecvError;
+use config::msg::client_to_server as input_msg;
+use config::msg::ServerToClient as OutputMsg;
+use ocj_config::auth::Token;
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
 
+async fn auth_mw(State(app): State<Arc<App>>, mut req: Request, next: Next) -> std::result::Result<Response, StatusCode> {
+    let token: Token = req.headers()
+        .get(config::auth::SECURE_TOKEN_HTTP_HEADER).ok_or(StatusCode::LOCKED)?
+        .to_str().or(Err(StatusCode::LOCKED))?
+        .parse().or(Err(StatusCode::LOCKED))?;
+    match app.auth.check_client_token(&token).await {
+        Ok(client_id) => {
+            req.extensions_mut().insert(client_id);
+            Ok(next.run(req).await)
+        }
+        Err(OcjError::Auth(_)) => Err(StatusCode::LOCKED),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+mod submissions {
+    use super::*;
+    use crate::config::solution;
+    pub async fn submit(State(app): State<Arc<App>>, Extension(client_id): Extension<client::Id>, Json(msg): Json<input_msg::submissions::Submit>) -> impl IntoResponse {
+        match app.submit(client_id, msg).await {
+            Ok(id) => Json::from(OutputMsg::Ok(id)),
+            Err(e) => {
+                log::warn!("submission rejected: {e}");
+                Json::from(OutputMsg::Err(e.to_string().into()))
+            }
+        }
+    }
+
+    // contestants see the failed test and the compiler output, not the per-test details with runtime error stderr
+    fn hide_tests(info: &mut solution::Info) {
+        if let Some(result) = info.result.as_mut() {
+            result.tests.clear();
+        }
+    }
+
+    pub async fn list(State(app): State<Arc<App>>, Extension(client_id): Extension<client::Id>) -> impl IntoResponse {
+        let mut infos = app.submissions(Some(client_id)).await;
+        infos.iter_mut().for_each(hide_tests);
+        Json::from(OutputMsg::<_>::Ok(infos))
+    }
+
+    // polled while the submission is judged to show its progress
+    pub async fn get(State(app): State<Arc<App>>, Extension(client_id): Extension<client::Id>, Path(id): Path<solution::Id>) -> impl IntoResponse {
+        match app.submission(id, Some(client_id)).await {
+            Ok(mut info) => {
+                hide_tests(&mut info);
+                Json::from(OutputMsg::Ok(info))
+            }
+            Err(e) => Json::from(OutputMsg::Err(e.to_string().into())),
+        }
+    }
+}
+
+pub async fn scoreboard(State(app): State<Arc<App>>) -> impl IntoResponse {
+    Json::from(OutputMsg::<_>::Ok(app.scoreboard(true).await))
+}
+
+pub async fn langs(State(app): State<Arc<App>>) -> impl IntoResponse {
+    Json::from(OutputMsg::<_>::Ok(app.machine.langs().await))
+}
+
+pub async fn statements(State(app): State<Arc<App>>) -> impl IntoResponse {
+    match app.statements().await {
+        Ok(data) => Json::from(OutputMsg::Ok(data)),
+        Err(e) => Json::from(OutputMsg::Err(e.to_string().into())),
+    }
+}
+
+pub async fn events(State(app): State<Arc<App>>) -> Sse<impl Stream<Item = std::result::Result<Event, Infallible>>> {
+    let stream = futures::stream::unfold(app.events.subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((Ok(Event::default().json_data(event).unwrap()), receiver)),
+                Err(RecvError::Lagged(n)) => log::warn!("client events receiver lagged by {n}"),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+mod auth {
+    use super::*;
+    pub async fn login(State(app): State<Arc<App>>, Json(msg): Json<input_msg::auth::Login>) -> impl IntoResponse {
+        match app.client_login(&msg).await {
+            Ok(token) => Json::from(OutputMsg::Ok(token)).into_response(),
+            Err(OcjError::Auth(e)) => {
+                log::warn!("participant login failed: {e}");
+                StatusCode::LOCKED.into_response()
+            }
+            Err(e) => Json::from(OutputMsg::<Token>::Err(e.to_string().into())).into_response(),
+        }
+    }
+}
+
+pub fn router(app: Arc<App>) -> Router<()> {
+    let submissions: Router<_> = Router::new()
+        .route("/", post(submissions::submit).get(submissions::list))
+        .route("/{id}", get(submissions::get))
+        .layer(middleware::from_fn_with_state(app.clone(), auth_mw))
+        .with_state(app.clone());
+    let auth: Router<_> = Router::new()
+        .route("/login", post(auth::login))
+        .with_state(app.clone());
+    Router::new()
+        .nest("/submissions", submissions)
+        .nest("/auth", auth)
+        .route("/scoreboard", get(scoreboard))
+        .route("/statements", get(statements))
+        .route("/langs", get(langs))
+        .route("/events", get(events))
+        .with_state(app.clone())
+}
 
 pub async fn server(app: Arc<App>) -> Result<()> {
     let addr = SocketAddr::new(app.ip, config::port::HTTP_FOR_CLIENT);
@@ -15,13 +133,13 @@ pub async fn server(app: Arc<App>) -> Result<()> {
         }
     };
 
-    let router = Router::new();
+    let router = router(app);
 
     log::info!("server running on port: {}", addr);
-    if let Err(e) = axum::serve(listner, router).await {
+    if let Err(e) = axum::serve(listner, router.into_make_service_with_connect_info::<SocketAddr>()).await {
         log::error!("server running with error {:?}", e);
         Err(e.into())
     } else {
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -1,33 +1,85 @@
 use tokio::{net::TcpStream, sync::Mutex};
-use tokio_tungstenite::{accept_async, tungstenite::Message};
-use futures::{SinkExt, StreamExt};
+use tokio_tungstenite::{accept_async, tungstenite::Message, WebSocketStream};
+use futures::{stream::{SplitSink, SplitStream}, SinkExt, StreamExt};
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
-use crate::{config, machine::{InputMsg, Machine}, App, Result};
+use crate::{config::{self, auth::Token, machine::{Id, HEARTBEAT_PERIOD, HEARTBEAT_TIMEOUT}, solution::{self, LangInfo}}, machine::{InputMsg, Machine, OutputMsg}, App, Result};
+
+// a connection that does not finish the handshake or introduce itself in this time is closed
+const INIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Sender = Mutex<SplitSink<WebSocketStream<TcpStream>, Message>>;
+type Init = (Id, Token, u16, Vec<LangInfo>, Vec<solution::Id>);
+
+// none if the connection is closed or the first message is not init
+async fn receive_init(receiver: &mut SplitStream<WebSocketStream<TcpStream>>, sender: &Sender) -> Result<Option<Init>> {
+    loop {
+        match receiver.next().await {
+            Some(Ok(Message::Text(data))) => return match serde_json::from_str::<InputMsg>(&data) {
+                Ok(InputMsg::Init { id, token, slots, langs, judging }) => Ok(Some((id, token, slots, langs, judging))),
+                _ => {
+                    log::warn!("machine did not send init message");
+                    Ok(None)
+                }
+            },
+            Some(Ok(Message::Ping(b))) => sender.lock().await.send(Message::Pong(b)).await?,
+            Some(Ok(_)) => {},
+            Some(Err(_)) | None => return Ok(None),
+        }
+    }
+}
 
 async fn ws_connect(stream: TcpStream, app: Arc<App>) -> Result<()> {
-    let machine_service = &app.machine;
-	let websocket = match accept_async(stream).await {
-        Err(e) => {
+	let websocket = match tokio::time::timeout(INIT_TIMEOUT, accept_async(stream)).await {
+        Err(_) => {
+            log::warn!("machine did not finish websocket handshake in {} s", INIT_TIMEOUT.as_secs());
+            return Ok(());
+        },
+        Ok(Err(e)) => {
             log::error!("while conecting with websockets: {e:?}");
             return Err(e.into());
         },
-        Ok(r) => r,
+        Ok(Ok(r)) => r,
     };
 
 	let (sender, mut receiver) = websocket.split();
     let sender = Arc::new(Mutex::new(sender));
 
+    // the machine introduces itself first
+    let (id, token, slots, langs, judging) = match tokio::time::timeout(INIT_TIMEOUT, receive_init(&mut receiver, &sender)).await {
+        Ok(Ok(Some(init))) => init,
+        Ok(Ok(None)) => return Ok(()),
+        Ok(Err(e)) => return Err(e),
+        Err(_) => {
+            log::warn!("machine did not send init message in {} s", INIT_TIMEOUT.as_secs());
+            return Ok(());
+        }
+    };
+
+    // nothing, tests included, is sent before the token is checked
+    let name = match app.auth.check_machine_token(&token).await {
+        Ok(name) => name,
+        Err(e) => {
+            log::warn!("machine [{id}] is not authenticated: {e}");
+            reject(&sender, OutputMsg::Unauthorized).await?;
+            return Ok(());
+        }
+    };
+    // a random id may repeat, the machine with the other token makes a new one
+    if !app.machine.claim_id(id, &name).await {
+        log::warn!("machine [{id}] <{name}> uses the id of a machine with another token");
+        reject(&sender, OutputMsg::IdTaken).await?;
+        return Ok(());
+    }
+
     let (i_ch_sender, i_ch_receiver) = tokio::sync::mpsc::unbounded_channel();
     let (o_ch_sender, mut o_ch_receiver) = tokio::sync::mpsc::unbounded_channel();
 
-    let id = rand::random::<u16>();
-
-    machine_service.add_machine(Machine::new(id, o_ch_sender, i_ch_receiver)).await;
+    let machine = app.add_machine(Machine::new(id, name, slots, o_ch_sender, i_ch_receiver), langs, judging).await;
 
     let sender_clone = Arc::clone(&sender);
-    let i_task: tokio::task::JoinHandle<Result<()>> = tokio::spawn(async move {
+    let mut i_task: tokio::task::JoinHandle<Result<()>> = tokio::spawn(async move {
         while let Some(msg) = receiver.next().await {
             if let Ok(msg) = msg {
                 match msg {
@@ -35,7 +87,10 @@ async fn ws_connect(stream: TcpStream, app: Arc<App>) -> Result<()> {
                         sender_clone.lock().await.send(Message::Pong(b)).await?;
                     },
                     Message::Text(data) => {
-                        i_ch_sender.send(serde_json::from_str::<InputMsg>(&data.to_string()).unwrap()).unwrap();
+                        match serde_json::from_str::<InputMsg>(&data) {
+                            Ok(msg) => i_ch_sender.send(msg).unwrap(),
+                            Err(e) => log::error!("incorrect message from machine [{id}]: {e}"),
+                        }
                     },
                     _ => {},
                 }
@@ -47,25 +102,58 @@ async fn ws_connect(stream: TcpStream, app: Arc<App>) -> Result<()> {
         Ok(())
     });
 
-    let o_task: tokio::task::JoinHandle<Result<()>> = tokio::spawn(async move {
+    let sender_revoke = Arc::clone(&sender);
+    let mut o_task: tokio::task::JoinHandle<Result<()>> = tokio::spawn(async move {
         while let Some(msg) = o_ch_receiver.recv().await {
             sender.lock().await.send(Message::Text(serde_json::to_string(&msg).unwrap().into())).await?
         }
         Ok(())
     });
 
-    // app.judge(crate::Solution{code: "code".to_string(), lang: crate::solution::Lang::Cpp, task_number: 12, id: 1332}).await;
+    let watchdog_machine = machine.clone();
+    let watchdog = async move {
+        let mut interval = tokio::time::interval(HEARTBEAT_PERIOD);
+        loop {
+            interval.tick().await;
+            if watchdog_machine.last_heartbeat.lock().await.elapsed() > HEARTBEAT_TIMEOUT {
+                return;
+            }
+        }
+    };
 
-    tokio::select! {
-        r = i_task => log::error!("input machine task error {:?}", r),
-        r = o_task => log::error!("output machine task error {:?}", r),
+    // a closed connection may be a network glitch, a silent or revoked machine is gone
+    let lost = tokio::select! {
+        r = &mut i_task => { log::error!("input machine task error {:?}", r); false },
+        r = &mut o_task => { log::error!("output machine task error {:?}", r); false },
+        _ = watchdog => { log::warn!("machine [{id}] sent no heartbeat for {} s", HEARTBEAT_TIMEOUT.as_secs()); true },
+        _ = machine.revoked.notified() => {
+            log::warn!("machine [{id}] <{}> is revoked", machine.name);
+            o_task.abort();
+            _ = reject(&sender_revoke, OutputMsg::Unauthorized).await;
+            true
+        },
+    };
+    // the connection is closed when both halves are dropped
+    i_task.abort();
+    o_task.abort();
+
+    if lost {
+        app.remove_machine(&machine).await;
+    } else {
+        app.disconnect_machine(&machine).await;
     }
 
-    machine_service.remove_machine(id).await;
-
     Ok(())
 } 
 
+// the machine stops on the message, so the connection is closed after it
+async fn reject(sender: &Sender, msg: OutputMsg) -> Result<()> {
+    let mut sender = sender.lock().await;
+    sender.send(Message::Text(serde_json::to_string(&msg).unwrap().into())).await?;
+    sender.close().await?;
+    Ok(())
+}
+
 pub async fn server(app: Arc<App>) -> Result<()> {
     let ws_addr = SocketAddr::new(app.ip, config::port::WS_FOR_MACHINE);
     let listner = match tokio::net::TcpListener::bind(ws_addr).await {
@@ -0,0 +1,183 @@
+use std::{path::Path, time::SystemTime};
+
+use serde::{Serialize, Deserialize};
+use tokio::{fs::{self, File, OpenOptions}, io::AsyncWriteExt, sync::Mutex};
+
+use crate::{client::{self, Participant}, config::{contest::Time, scoreboard::{Rules, Unfreeze}}, submission::{Judged, Submission}, OcjError, Result};
+
+pub const DIR: &str = "data";
+pub const LOG: &str = "log.jsonl";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Record {
+    Submission(Submission),
+    Judged(Judged),
+    ContestTime(Time),
+    ContestPaused(SystemTime),
+    ContestResumed,
+    ContestFinished,
+    ScoreboardRules(Rules),
+    Unfreeze(Unfreeze),
+    Participant(Participant),
+    // only hashes of tokens are stored, like invite codes of participants
+    ClientToken {
+        token_hash: u128,
+        client_id: client::Id,
+    },
+    MachineToken {
+        token_hash: u128,
+        name: Box<str>,
+    },
+    MachineTokenRevoked(Box<str>),
+}
+
+pub struct Service {
+    log: Mutex<File>,
+}
+
+impl Service {
+    pub async fn init(dir: &Path) -> Result<(Self, Vec<Record>)> {
+        fs::create_dir_all(dir).await?;
+        let path = dir.join(LOG);
+        let records = Self::load(&path).await?;
+        let log = OpenOptions::new().create(true).append(true).open(&path).await?;
+        log::info!("storage loaded: {} records from {}", records.len(), path.display());
+        Ok((Self { log: Mutex::new(log) }, records))
+    }
+
+    async fn load(path: &Path) -> Result<Vec<Record>> {
+        let data = match fs::read_to_string(path).await {
+            Ok(d) => d,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        // only the last line can be cut off by a crash in the middle of a write,
+        // any other broken record needs a look from the admin before the log is changed
+        let mut records = Vec::new();
+        let mut valid_len = 0;
+        for (number, line) in data.split_inclusive('\n').enumerate() {
+            if !line.ends_with('\n') {
+                log::error!("storage record {} is not complete", number + 1);
+                break;
+            }
+            let record = serde_json::from_str::<Record>(line).map_err(|e| OcjError::BrokenRecord(number + 1, e))?;
+            records.push(record);
+            valid_len += line.len();
+        }
+
+        if valid_len < data.len() {
+            log::warn!("storage log is truncated to the last complete record");
+            OpenOptions::new().write(true).open(path).await?.set_len(valid_len as u64).await?;
+        }
+        Ok(records)
+    }
+
+    pub async fn append(&self, record: &Record) -> Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        let mut log = self.log.lock().await;
+        log.write_all(line.as_bytes()).await?;
+        log.sync_data().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::{Duration, UNIX_EPOCH}};
+
+    use super::*;
+
+    // a fresh directory for every test
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ocj_storage_{}_{name}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn time(start: u64) -> Record {
+        Record::ContestTime(Time { start: UNIX_EPOCH + Duration::from_secs(start), duration: None, freeze: None })
+    }
+
+    fn starts(records: &[Record]) -> Vec<u64> {
+        records.iter().map(|r| match r {
+            Record::ContestTime(t) => t.start.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            r => panic!("unexpected record {r:?}"),
+        }).collect()
+    }
+
+    async fn write_records(dir: &Path) {
+        let (storage, records) = Service::init(dir).await.unwrap();
+        assert!(records.is_empty());
+        storage.append(&time(1)).await.unwrap();
+        storage.append(&time(2)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn records_are_loaded_in_order() {
+        let dir = dir("order");
+        write_records(&dir).await;
+        let (_, records) = Service::init(&dir).await.unwrap();
+        assert_eq!(starts(&records), [1, 2]);
+        _ = fs::remove_dir_all(&dir).await;
+    }
+
+    #[tokio::test]
+    async fn partial_last_record_is_truncated() {
+        let dir = dir("partial");
+        write_records(&dir).await;
+        let path = dir.join(LOG);
+        let valid_len = fs::metadata(&path).await.unwrap().len();
+        // the server was stopped in the middle of a write
+        OpenOptions::new().append(true).open(&path).await.unwrap().write_all(b"{\"ContestTime\":").await.unwrap();
+
+        let (storage, records) = Service::init(&dir).await.unwrap();
+        assert_eq!(starts(&records), [1, 2]);
+        assert_eq!(fs::metadata(&path).await.unwrap().len(), valid_len);
+
+        // new records follow the last valid one
+        storage.append(&time(3)).await.unwrap();
+        let (_, records) = Service::init(&dir).await.unwrap();
+        assert_eq!(starts(&records), [1, 2, 3]);
+        _ = fs::remove_dir_all(&dir).await;
+    }
+
+    #[tokio::test]
+    async fn token_hashes_are_stored_instead_of_tokens() {
+        let dir = dir("tokens");
+        let (storage, _) = Service::init(&dir).await.unwrap();
+        let token = crate::auth::gen_token().unwrap();
+        let token_hash = crate::auth::hash_token(&token);
+        storage.append(&Record::MachineToken { token_hash, name: "m1".into() }).await.unwrap();
+
+        let data = fs::read_to_string(dir.join(LOG)).await.unwrap();
+        assert!(!data.contains(&token.to_string()));
+        let (_, records) = Service::init(&dir).await.unwrap();
+        match &records[..] {
+            [Record::MachineToken { token_hash: h, name }] => assert_eq!((*h, name.as_ref()), (token_hash, "m1")),
+            r => panic!("unexpected records {r:?}"),
+        }
+        _ = fs::remove_dir_all(&dir).await;
+    }
+
+    #[tokio::test]
+    async fn broken_record_is_an_error() {
+        let dir = dir("broken");
+        write_records(&dir).await;
+        let path = dir.join(LOG);
+        // a complete line that is not a record, followed by a valid one
+        let mut log = OpenOptions::new().append(true).open(&path).await.unwrap();
+        log.write_all(b"{\"ContestTime\":\n").await.unwrap();
+        log.write_all(format!("{}\n", serde_json::to_string(&time(3)).unwrap()).as_bytes()).await.unwrap();
+        let data = fs::read(&path).await.unwrap();
+
+        match Service::init(&dir).await {
+            Err(OcjError::BrokenRecord(3, _)) => {}
+            Err(e) => panic!("unexpected error {e:?}"),
+            Ok((_, records)) => panic!("loaded {} records", records.len()),
+        }
+        assert_eq!(fs::read(&path).await.unwrap(), data);
+        _ = fs::remove_dir_all(&dir).await;
+    }
+}
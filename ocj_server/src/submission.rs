@@ -0,0 +1,107 @@
+use std::{collections::HashMap, time::SystemTime};
+
+use serde::{Serialize, Deserialize};
+use tokio::sync::Mutex;
+
+use crate::{client, config::{msg::client_to_server::submissions::Submit, solution::{self, JudgeResult, Solution}}, OcjError, Result};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Judged {
+    pub time: SystemTime,
+    pub result: JudgeResult,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Submission {
+    pub client_id: client::Id,
+    pub solution: Solution,
+    pub time: SystemTime,
+    pub judged: Option<Judged>,
+    // reported by the machine while judging, not stored
+    #[serde(skip)]
+    pub progress: Option<solution::Progress>,
+}
+
+impl Submission {
+    pub fn info(&self, participant: Box<str>) -> solution::Info {
+        solution::Info {
+            id: self.solution.id,
+            participant,
+            problem_number: self.solution.problem_number,
+            lang: self.solution.lang.clone(),
+            time: self.time,
+            result: self.judged.as_ref().map(|j| j.result.clone()),
+            progress: self.progress.clone(),
+        }
+    }
+}
+
+pub struct Service {
+    pub submissions: Mutex<HashMap<solution::Id, Submission>>,
+    last_id: Mutex<solution::Id>,
+}
+
+impl Service {
+    pub fn init() -> Self {
+        Self {
+            submissions: Mutex::new(HashMap::new()),
+            last_id: Mutex::new(0),
+        }
+    }
+
+    pub async fn add(&self, client_id: client::Id, submit: Submit) -> Submission {
+        let mut submissions = self.submissions.lock().await;
+        let mut last_id = self.last_id.lock().await;
+        *last_id += 1;
+        let submission = Submission {
+            client_id,
+            solution: Solution {
+                code: submit.code,
+                lang: submit.lang,
+                problem_number: submit.problem_number,
+                id: *last_id,
+            },
+            time: SystemTime::now(),
+            judged: None,
+            progress: None,
+        };
+        submissions.insert(*last_id, submission.clone());
+        submission
+    }
+
+    pub async fn restore(&self, submission: Submission) {
+        let mut submissions = self.submissions.lock().await;
+        let mut last_id = self.last_id.lock().await;
+        let id = submission.solution.id;
+        *last_id = (*last_id).max(id);
+        submissions.insert(id, submission);
+    }
+
+    pub async fn set_judged(&self, judged: Judged) -> Result<()> {
+        let mut submissions = self.submissions.lock().await;
+        let id = judged.result.solution_id;
+        let submission = submissions.get_mut(&id).ok_or(OcjError::SolutionNotFound(id))?;
+        if submission.judged.is_some() {
+            log::warn!("solution [{id}] was judged again");
+        }
+        submission.judged = Some(judged);
+        submission.progress = None;
+        Ok(())
+    }
+
+    // none puts the submission back in the queue
+    pub async fn set_progress(&self, id: solution::Id, progress: Option<solution::Progress>) -> Result<()> {
+        let mut submissions = self.submissions.lock().await;
+        let submission = submissions.get_mut(&id).ok_or(OcjError::SolutionNotFound(id))?;
+        // a late message must not hide the result
+        if submission.judged.is_none() {
+            submission.progress = progress;
+        }
+        Ok(())
+    }
+
+    pub async fn is_judged(&self, id: solution::Id) -> Result<bool> {
+        let submissions = self.submissions.lock().await;
+        Ok(submissions.get(&id).ok_or(OcjError::SolutionNotFound(id))?.judged.is_some())
+    }
+}
@@ -5,7 +5,7 @@ use std::io::{stdin, stdout, Error, Write};
 use chrono::{Local, NaiveDateTime, TimeZone};
 use colored::Colorize;
 
-use ocj_config::{self as config, auth::Token, msg::{admin_to_server as output_msg, ServerToAdmin as InputMsg}};
+use ocj_config::{self as config, auth::Token, contest::{UpdateDuration, UpdateTime}, msg::{admin_to_server as output_msg, ServerToAdmin as InputMsg}, participant::{Credentials, New}, scoreboard::{Rules, Scoreboard, Unfreeze}, machine, solution::{self, Info, Progress, Verdict}, tests::PackageError};
 
 fn parse_date(s: Option<Box<str>>) -> Result<chrono::NaiveDate, &'static str> {
     let date = if let Some(d) = s {d} else {
@@ -54,6 +54,106 @@ fn parse_duration(s: Option<Box<str>>) -> Result<Option<std::time::Duration>, &'
     Ok(duration.map(|d| chrono::Duration::minutes(d.into()).to_std().unwrap()))
 }
 
+fn parse_participants(s: Option<Box<str>>) -> Result<Vec<New>, &'static str> {
+    let path = if let Some(p) = s {p} else {
+        return Err("argument not found");
+    };
+    let data = if let Ok(d) = std::fs::read_to_string(path.as_ref()) {d} else {
+        return Err("file can't be read");
+    };
+
+    let mut participants = Vec::new();
+    for line in data.lines().filter(|l| !l.trim().is_empty()) {
+        let mut fields = line.split(',').map(|f| Box::<str>::from(f.trim()));
+        let (login, name) = if let (Some(l), Some(n)) = (fields.next(), fields.next()) {(l, n)} else {
+            return Err("line must be <login>,<name>[,<password>]");
+        };
+        participants.push(New {
+            login,
+            name,
+            password: fields.next(),
+        });
+    }
+    Ok(participants)
+}
+
+fn print_scoreboard(scoreboard: &Scoreboard) {
+    if scoreboard.frozen {
+        println!("{}", "[frozen]".bright_cyan());
+    }
+    for row in &scoreboard.rows {
+        let total = match scoreboard.rules {
+            Rules::Icpc => format!("{} {}", row.solved, row.penalty),
+            Rules::Ioi => format!("{}", row.score),
+        };
+        let problems: Vec<String> = row.problems.iter().map(|p| match scoreboard.rules {
+            Rules::Icpc if p.pending > 0 && !p.solved => format!("{}:?{}", p.problem_number, p.wrong_attempts + p.pending),
+            Rules::Icpc if p.solved => format!("{}:+{}", p.problem_number, if p.wrong_attempts > 0 {p.wrong_attempts.to_string()} else {String::new()}),
+            Rules::Icpc if p.wrong_attempts > 0 => format!("{}:-{}", p.problem_number, p.wrong_attempts),
+            Rules::Icpc => format!("{}:.", p.problem_number),
+            Rules::Ioi => format!("{}:{}", p.problem_number, p.score),
+        }).collect();
+        println!("{:>3} {} {} {}", row.place, row.participant.bold(), total.bright_blue(), problems.join(" "));
+    }
+}
+
+fn verdict_str(verdict: &Verdict) -> colored::ColoredString {
+    let s = format!("{verdict:?}").to_uppercase();
+    match verdict {
+        Verdict::Ok => s.bright_green(),
+        Verdict::Skipped => s.bright_black(),
+        _ => s.red(),
+    }
+}
+
+fn print_submission(info: &Info) {
+    let result = match &info.result {
+        Some(r) => {
+            let test = r.failed_test.map_or(String::new(), |t| format!(" on test {t}"));
+            format!("{}{test} {} {} ms / {} KB", verdict_str(&r.verdict), r.score.to_string().bright_blue(), r.time, r.memory)
+        }
+        None => match &info.progress {
+            Some(Progress::Compiling) => "compiling".yellow().to_string(),
+            Some(Progress::Running { test, test_count }) => format!("{} {test}/{test_count}", "running on test".yellow()),
+            Some(Progress::Checking) => "checking".yellow().to_string(),
+            None => "in queue".bright_black().to_string(),
+        },
+    };
+    println!("{:>5} {} {} {}", info.id, info.participant.bold(), info.problem_number.to_string().bright_blue(), result);
+}
+
+fn print_tests(info: &Info) {
+    let result = if let Some(r) = &info.result {r} else {
+        return;
+    };
+    if let Some(output) = &result.compile_output {
+        println!("{}", output.bright_black());
+    }
+    for g in &result.groups {
+        let state = if g.skipped {"skipped".bright_black()} else if g.passed {"passed".bright_green()} else {"failed".red()};
+        println!("  group {} {} {}", g.group, state, g.points.to_string().bright_blue());
+    }
+    for t in &result.tests {
+        let exit = match (t.exit_code, t.signal) {
+            (Some(c), _) => format!("exit {c}"),
+            (None, Some(s)) => format!("signal {s}"),
+            (None, None) => String::new(),
+        };
+        println!("  {:>3} {} {} ms / {} KB {} {}", t.test_number, verdict_str(&t.verdict), t.time, t.memory, exit.bright_black(), t.comment);
+        if let Some(stderr) = &t.stderr {
+            println!("{}", stderr.bright_black());
+        }
+    }
+}
+
+fn print_credentials(credentials: &Credentials) {
+    if let Some(code) = &credentials.invite_code {
+        println!("{} {}", credentials.login.bold(), code);
+    } else {
+        println!("{} {}", credentials.login.bold(), "(password)".bright_black());
+    }
+}
+
 fn main() -> std::io::Result<()> {
     let args: Box<[String]> = std::env::args().collect();
 
@@ -99,8 +199,37 @@ fn main() -> std::io::Result<()> {
                 println!(r#"
                 ===::OCJ::===
                 
+                tests.upd
+                tests.errors  (package errors reported by machines)
+                statements.upd
+
                 for date use <dd-mm-yyyy> or <today>
                 for time use <hh:mm> or <now>
+
+                contest.state.ready <date> <time> <duration|none> [freeze]
+                durations are in minutes, freeze is counted back from the end
+                contest.state.finish
+                contest.state.pause
+                contest.state.resume  (end time moves by the paused span)
+                contest.time.start <date> <time>
+                contest.time.duration <add|sub> <minutes>
+                contest.time.duration set <minutes|none>
+
+                contest.rules <icpc|ioi>
+                scoreboard
+                scoreboard.public
+                scoreboard.unfreeze [problem]
+
+                participants.add <login> <name> [password]
+                participants.import <file>  (lines <login>,<name>[,<password>])
+                participants without password get an invite code
+
+                submissions
+                submission <id>  (per-test report)
+
+                machines  (connected machines and their last heartbeat)
+                machines.token <name>  (put it in machine_token next to ocj_machine)
+                machines.revoke <name>  (its connections are closed)
                 "#)
             }
          
@@ -120,6 +249,102 @@ fn main() -> std::io::Result<()> {
                 }
             }
 
+            "tests.errors" => {
+                let res: InputMsg<Vec<PackageError>> = if let Ok(r) = client.get(format!("{ip}/contest/tests/errors"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+
+                match res {
+                    InputMsg::Ok(errors) if errors.is_empty() => println!("{}", "all packages are fine".blue()),
+                    InputMsg::Ok(errors) => {
+                        for e in &errors {
+                            println!("{} {}", format!("problem {}", e.problem_number).bold(), e.error.red());
+                        }
+                    }
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
+            "machines" => {
+                let res: InputMsg<Vec<machine::Info>> = if let Ok(r) = client.get(format!("{ip}/machines"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+
+                match res {
+                    InputMsg::Ok(machines) if machines.is_empty() => println!("{}", "no machines are connected".yellow()),
+                    InputMsg::Ok(machines) => {
+                        for m in &machines {
+                            let langs: Vec<&str> = m.langs.iter().map(|l| l.0.as_ref()).collect();
+                            println!("{:>5} {} {}/{} slots busy, heartbeat {} s ago, {}", m.id.to_string().bold(), m.name, m.tasks, m.slots, m.last_heartbeat, langs.join(" ").bright_black());
+                            if let Some(h) = &m.health {
+                                println!("      load {:.2}, free {} MB memory / {} MB disk, judging {:?}", h.load, h.free_memory, h.free_disk, h.judging);
+                            }
+                        }
+                    }
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
+            "machines.token" => {
+                let name = if let Some(n) = cmd.get(1) {n.clone()} else {
+                    println!("{} {}", "machine".red().bold(), "name not found".red());
+                    continue;
+                };
+                let res: InputMsg<Token> = if let Ok(r) = client.post(format!("{ip}/machines/tokens"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .json(&name)
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+
+                match res {
+                    InputMsg::Ok(t) => println!("{} {}", name.bold(), t.to_string()),
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
+            "machines.revoke" => {
+                let name = if let Some(n) = cmd.get(1) {n.clone()} else {
+                    println!("{} {}", "machine".red().bold(), "name not found".red());
+                    continue;
+                };
+                let res: InputMsg<()> = if let Ok(r) = client.post(format!("{ip}/machines/revoke"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .json(&name)
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+
+                match res {
+                    InputMsg::Ok(()) => println!("{}", "revoked".blue()),
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
+            "statements.upd" => {
+                let statements = file::get_compressed_statements()?;
+                let msg: output_msg::contest::statements::Update = statements;
+                let res: InputMsg<()> = if let Ok(r) = client.patch(format!("{ip}/contest/statements"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .json(&msg)
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+                match res {
+                    InputMsg::Ok(()) => println!("{}", "statements was updated".blue()),
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
             "contest.state.ready" => {
                 let start_date = match parse_date(cmd.get(1).cloned()) {
                     Ok(d) => d,
@@ -145,6 +370,17 @@ fn main() -> std::io::Result<()> {
                     }
                 };
 
+                let freeze = match cmd.get(4).cloned() {
+                    None => None,
+                    f => match parse_duration(f) {
+                        Ok(o) => o,
+                        Err(e) => {
+                            println!("{} {}", "freeze".red().bold(), e.red());
+                            continue;
+                        }
+                    }
+                };
+
                 let start = Local.from_local_datetime( &NaiveDateTime::new(start_date, start_time)).unwrap();
 
                 if start + chrono::Duration::seconds(1) < Local::now() {
@@ -157,6 +393,7 @@ fn main() -> std::io::Result<()> {
                     .json(&output_msg::contest::state::SetReady {
                         start: start.into(),
                         duration: duration,
+                        freeze,
                     })
                     .send().unwrap()
                     .json().unwrap();
@@ -167,7 +404,231 @@ fn main() -> std::io::Result<()> {
                     println!("{} {}", "new contest state:".bright_blue(), "READY".bold().blue());
                 }
             }
-            
+
+            "contest.state.finish" | "contest.state.pause" | "contest.state.resume" => {
+                let (path, state) = match cmd[0].as_ref() {
+                    "contest.state.finish" => ("finish", "FINISHED"),
+                    "contest.state.pause" => ("pause", "PAUSED"),
+                    _ => ("resume", "GOING"),
+                };
+
+                let res: InputMsg<()> = if let Ok(r) = client.post(format!("{ip}/contest/state/{path}"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+
+                match res {
+                    InputMsg::Ok(()) => println!("{} {}", "new contest state:".bright_blue(), state.bold().blue()),
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
+            "contest.time.start" | "contest.time.duration" => {
+                let update: output_msg::contest::time::Update = if cmd[0].as_ref() == "contest.time.start" {
+                    let start_date = match parse_date(cmd.get(1).cloned()) {
+                        Ok(d) => d,
+                        Err(e) => {
+                            println!("{} {}", "start date".red().bold(), e.red());
+                            continue;
+                        }
+                    };
+
+                    let start_time = match parse_time(cmd.get(2).cloned()) {
+                        Ok(t) => t,
+                        Err(e) => {
+                            println!("{} {}", "start time".red().bold(), e.red());
+                            continue;
+                        }
+                    };
+
+                    UpdateTime::Start(Local.from_local_datetime(&NaiveDateTime::new(start_date, start_time)).unwrap().into())
+                } else {
+                    let duration = match parse_duration(cmd.get(2).cloned()) {
+                        Ok(o) => o,
+                        Err(e) => {
+                            println!("{} {}", "duration".red().bold(), e.red());
+                            continue;
+                        }
+                    };
+
+                    UpdateTime::Duration(match (cmd.get(1).map(|s| s.as_ref()), duration) {
+                        (Some("add"), Some(d)) => UpdateDuration::Add(d),
+                        (Some("sub"), Some(d)) => UpdateDuration::Sub(d),
+                        (Some("set"), d) => UpdateDuration::Set(d),
+                        _ => {
+                            println!("{} {}", "duration".red().bold(), "use <add|sub> <minutes> or set <minutes|none>".red());
+                            continue;
+                        }
+                    })
+                };
+
+                let res: InputMsg<()> = if let Ok(r) = client.patch(format!("{ip}/contest/time"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .json(&update)
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+
+                match res {
+                    InputMsg::Ok(()) => println!("{}", "contest time was updated".blue()),
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
+            "contest.rules" => {
+                let rules: output_msg::contest::rules::Set = match cmd.get(1).map(|s| s.as_ref()) {
+                    Some("icpc") => Rules::Icpc,
+                    Some("ioi") => Rules::Ioi,
+                    _ => {
+                        println!("{} {}", "rules".red().bold(), "argument is incorrect".red());
+                        continue;
+                    }
+                };
+
+                let res: InputMsg<()> = if let Ok(r) = client.patch(format!("{ip}/contest/rules"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .json(&rules)
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+
+                match res {
+                    InputMsg::Ok(()) => println!("{} {:?}", "contest rules:".bright_blue(), rules),
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
+            "scoreboard" | "scoreboard.public" => {
+                let path = if cmd[0].as_ref() == "scoreboard" {""} else {"/public"};
+                let res: InputMsg<Scoreboard> = if let Ok(r) = client.get(format!("{ip}/contest/scoreboard{path}"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+
+                match res {
+                    InputMsg::Ok(s) => print_scoreboard(&s),
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
+            "scoreboard.unfreeze" => {
+                let unfreeze: output_msg::contest::scoreboard::Unfreeze = match cmd.get(1).map(|n| n.parse()) {
+                    None => Unfreeze::All,
+                    Some(Ok(n)) => Unfreeze::Problem(n),
+                    Some(Err(_)) => {
+                        println!("{} {}", "problem".red().bold(), "argument is incorrect".red());
+                        continue;
+                    }
+                };
+
+                let res: InputMsg<()> = if let Ok(r) = client.post(format!("{ip}/contest/scoreboard/unfreeze"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .json(&unfreeze)
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+
+                match res {
+                    InputMsg::Ok(()) => println!("{} {:?}", "scoreboard unfrozen:".bright_blue(), unfreeze),
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
+            "participants.add" => {
+                let (login, name) = if let (Some(l), Some(n)) = (cmd.get(1), cmd.get(2)) {(l.clone(), n.clone())} else {
+                    println!("{} {}", "participant".red().bold(), "argument not found".red());
+                    continue;
+                };
+
+                let res: InputMsg<Credentials> = if let Ok(r) = client.post(format!("{ip}/participants"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .json(&output_msg::participants::Add {
+                        login,
+                        name,
+                        password: cmd.get(3).cloned(),
+                    })
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+
+                match res {
+                    InputMsg::Ok(c) => print_credentials(&c),
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
+            "participants.import" => {
+                let participants: output_msg::participants::Import = match parse_participants(cmd.get(1).cloned()) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        println!("{} {}", "participants".red().bold(), e.red());
+                        continue;
+                    }
+                };
+
+                let res: InputMsg<Vec<Credentials>> = if let Ok(r) = client.post(format!("{ip}/participants/import"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .json(&participants)
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+
+                match res {
+                    InputMsg::Ok(credentials) => {
+                        for c in &credentials {
+                            print_credentials(c);
+                        }
+                        println!("{} {}", credentials.len(), "participants was imported".blue());
+                    }
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
+            "submissions" => {
+                let res: InputMsg<Vec<Info>> = if let Ok(r) = client.get(format!("{ip}/submissions"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+
+                match res {
+                    InputMsg::Ok(infos) => infos.iter().for_each(print_submission),
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
+            "submission" => {
+                let id: solution::Id = if let Some(Ok(id)) = cmd.get(1).map(|s| s.parse()) {id} else {
+                    println!("{} {}", "submission id".red().bold(), "argument is incorrect".red());
+                    continue;
+                };
+
+                let res: InputMsg<Info> = if let Ok(r) = client.get(format!("{ip}/submissions/{id}"))
+                    .header(config::auth::SECURE_TOKEN_HTTP_HEADER, token.to_string())
+                    .send() {r} else {
+                        println!("{}", "connection failed".red());
+                        continue;
+                    }.json().unwrap();
+
+                match res {
+                    InputMsg::Ok(info) => {
+                        print_submission(&info);
+                        print_tests(&info);
+                    }
+                    InputMsg::Err(e) => println!("{}", e.red()),
+                }
+            }
+
             "" => {
                 s_ch = ui::prev_command_line_str();
             }